@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::{
+    diagnostics::diagnostic::Diagnostic,
+    lexer::token::{Span, Token, TokenKind},
+};
+
+/// Everything that can go wrong while building the AST. Each variant keeps the token the
+/// parser was looking at so the error can be reported at the right place in the source.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// Found a token that can't appear here, `expected` describes what could have
+    UnexpectedToken { token: Token, expected: String },
+
+    /// A `(` or `{` was opened at `open` but `found` appeared where it should have been closed,
+    /// `open` is boxed to keep the error small
+    UnclosedDelimiter { open: Box<Token>, found: Token },
+
+    /// The number literal is too big for its type, `ty` is the suffix or the default type
+    InvalidNumber { token: Token, ty: &'static str },
+}
+
+impl ParseError {
+    /// The token the parser was looking at when the error occurred
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::UnexpectedToken { token, .. } => token,
+            Self::UnclosedDelimiter { found, .. } => found,
+            Self::InvalidNumber { token, .. } => token,
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.token().span
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::UnexpectedToken { expected, .. } => {
+                diag.with_label(format!("expected {expected}"))
+            }
+            Self::UnclosedDelimiter { open, .. } => diag
+                .with_label(format!("expected {}", closing(&open.kind)))
+                .with_secondary(open.span.clone(), format!("unclosed {}", open.kind)),
+            Self::InvalidNumber { ty, .. } => diag.with_label(format!("does not fit in `{ty}`")),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedToken { token, expected } => {
+                write!(f, "expected {expected}, found {}", token.kind)
+            }
+            Self::UnclosedDelimiter { open, found } => write!(
+                f,
+                "expected {} to close {}, found {}",
+                closing(&open.kind),
+                open.kind,
+                found.kind
+            ),
+            Self::InvalidNumber { token, ty } => {
+                write!(f, "{} is out of range for `{ty}`", token.kind)
+            }
+        }
+    }
+}
+
+/// The token that closes the delimiter `open`
+fn closing(open: &TokenKind) -> TokenKind {
+    match open {
+        TokenKind::LCurl => TokenKind::RCurl,
+        TokenKind::LBrac => TokenKind::RBrac,
+        _ => TokenKind::RPar,
+    }
+}
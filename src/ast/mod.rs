@@ -1,2 +1,13 @@
+pub mod error;
 pub mod node;
-pub mod parser;
\ No newline at end of file
+pub mod number;
+pub mod parser;
+
+/// Lexes and parses `src` for a test, which only gives it source that is valid
+#[cfg(test)]
+pub fn parse(src: &str) -> (Vec<crate::lexer::token::Token>, Vec<node::Node>) {
+    let mut lexer = crate::lexer::lexer::Lexer::new(src);
+    let tokens = lexer.scan().expect("source lexes").clone();
+    let ast = parser::Parser::new(&tokens).parse().expect("source parses");
+    (tokens, ast)
+}
@@ -1,24 +1,320 @@
-pub static BINARY_OPS: [u8; 6] = [
-    '+' as u8,
-    '-' as u8,
-    '*' as u8,
-    '/' as u8,
-    '%' as u8,
-    '^' as u8,
+use std::{fmt, rc::Rc};
+
+use crate::lexer::token::Span;
+
+use super::number::NumType;
+
+/// Every binary operator, operators spelt with more than one character are stored as a single
+/// byte: `==` is `=`, `!=` is `!`, `<=` is `L`, `>=` is `G`, `and` is `&` and `or` is `|`
+pub static BINARY_OPS: [u8; 14] = [
+    b'+', b'-', b'*', b'/', b'%', b'^', b'=', b'!', b'<', b'L', b'>', b'G', b'&', b'|',
 ];
 
+/// Precedence of prefix operators, binds tighter than `as` but looser than `^` so `-2 ^ 2` is `-(2 ^ 2)`
+pub const UNARY_PREC: u8 = 1;
+
+/// Precedence of `as`, binds tighter than `*` so `a * b as f64` is `a * (b as f64)`
+pub const AS_PREC: u8 = 1;
+
+/// Precedence of the operand of `not`, which takes in comparisons so `not a == b` is `not (a == b)`
+pub const NOT_PREC: u8 = 6;
+
+/// Returns how a binary operator byte is written in source
+pub fn op_str(op: u8) -> &'static str {
+    match op {
+        b'+' => "+",
+        b'-' => "-",
+        b'*' => "*",
+        b'/' => "/",
+        b'%' => "%",
+        b'^' => "^",
+        b'=' => "==",
+        b'!' => "!=",
+        b'<' => "<",
+        b'L' => "<=",
+        b'>' => ">",
+        b'G' => ">=",
+        b'&' => "and",
+        b'|' => "or",
+        _ => "?",
+    }
+}
+
+/// Returns how a unary operator byte is written in source, `not` is stored as `!`
+pub fn unary_op_str(op: u8) -> &'static str {
+    match op {
+        b'-' => "-",
+        b'!' => "!",
+        b'#' => "#",
+        b'&' => "&",
+        b'*' => "*",
+        _ => "?",
+    }
+}
+
 #[derive(Debug)]
 pub enum Node {
-    Integer(i32),
-    Number(f32),
+    /// Integer literal with its type, `i64` unless it had a suffix
+    Integer(i128, NumType),
+    /// Float literal with its type, `f64` unless it had a suffix
+    Number(f64, NumType),
+    Bool(bool),
+    /// String literal with its escapes already resolved
+    Str(String),
+    Ident(Ident),
 
+    /// Parenthesized expression, kept in the tree so the original grouping survives
+    Group(Box<Node>),
+
+    UnaryExpr(UnaryExpr),
     BinaryExpr(BinaryExpr),
+    /// `x as u8`
+    Cast(Cast),
+
+    /// `new x = 1`, `new mut x :: int = 1` or the shorthand `x := 1`
+    Let(Let),
+    /// `x = 1`, `x += 1` or `x -= 1`
+    Assign(Assign),
+
+    /// Function definitions are reference counted so the interpreter can hold on to them
+    Function(Rc<FunctionExpr>),
+    Call(CallExpr),
+    Return(Return),
+
+    /// `{ ... }` on its own, opens a new scope
+    Block(Vec<Node>),
+    If(IfStmt),
+    While(WhileStmt),
+    For(ForStmt),
+    /// Holds the span of the keyword
+    Break(Span),
+    Continue(Span),
+}
+
+#[derive(Debug)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// A prefix operator: `-` negation, `!`/`not` logical not, `#` length, `&` address or `*` deref
+#[derive(Debug)]
+pub struct UnaryExpr {
+    pub op: u8,
+    pub rhs: Box<Node>,
+    /// Span of the operator
+    pub span: Span,
+}
+
+/// Converts a number to another number type, `ty` is checked to be one by the checker
+#[derive(Debug)]
+pub struct Cast {
+    pub value: Box<Node>,
+    pub ty: Ident,
+    /// Span of `as`
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct BinaryExpr {
     pub op: u8,
-    pub prec: u8,
     pub lhs: Box<Node>,
     pub rhs: Box<Node>,
-}
\ No newline at end of file
+    /// Span of the operator
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Let {
+    pub name: Ident,
+    pub mutable: bool,
+    /// The type after `::`, if one was written
+    pub annotation: Option<Ident>,
+    pub value: Box<Node>,
+}
+
+#[derive(Debug)]
+pub struct Assign {
+    pub target: Ident,
+    /// `=` for a plain assignment, otherwise the binary operator applied before assigning
+    pub op: u8,
+    pub value: Box<Node>,
+    /// Span of the assignment operator
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ParameterExpr {
+    pub name: Ident,
+    pub annotation: Ident,
+    pub mutable: bool,
+}
+
+#[derive(Debug)]
+pub struct FunctionSignature {
+    pub name: Ident,
+    pub params: Vec<ParameterExpr>,
+    /// The type after `->`, functions without one don't return a value
+    pub returns: Option<Ident>,
+    /// Text of the `///` comments written above the function
+    pub doc: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct FunctionExpr {
+    pub signature: FunctionSignature,
+    pub body: Vec<Node>,
+}
+
+#[derive(Debug)]
+pub struct CallExpr {
+    pub callee: Ident,
+    pub args: Vec<Node>,
+    /// Span of the whole call including the arguments
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Return {
+    pub value: Option<Box<Node>>,
+    /// Span of the `return` keyword
+    pub span: Span,
+}
+
+/// A condition and the body that runs when it holds
+#[derive(Debug)]
+pub struct Branch {
+    pub cond: Node,
+    pub body: Vec<Node>,
+    /// Span of the keyword that starts the branch
+    pub span: Span,
+}
+
+/// `if cond { } elif cond { } else { }`, the first branch is the `if` and the rest are `elif`s
+#[derive(Debug)]
+pub struct IfStmt {
+    pub branches: Vec<Branch>,
+    pub otherwise: Option<Vec<Node>>,
+}
+
+#[derive(Debug)]
+pub struct WhileStmt {
+    pub cond: Box<Node>,
+    pub body: Vec<Node>,
+    /// Span of the `while` keyword
+    pub span: Span,
+}
+
+/// `for var in start..end { }`, the end of the range is exclusive
+#[derive(Debug)]
+pub struct ForStmt {
+    pub var: Ident,
+    pub start: Box<Node>,
+    pub end: Box<Node>,
+    /// Span of the whole end of the range, which literals inside it don't have
+    pub end_span: Span,
+    pub body: Vec<Node>,
+    /// Span of the `..`
+    pub span: Span,
+}
+
+/// Prints the node with every expression fully parenthesized so the shape of the tree is visible
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Integer(v, _) => write!(f, "{v}"),
+            Node::Number(v, _) => write!(f, "{v:?}"),
+            Node::Bool(v) => write!(f, "{v}"),
+            Node::Str(v) => write!(f, "{v:?}"),
+            Node::Ident(ident) => write!(f, "{}", ident.name),
+            Node::Group(inner) => write!(f, "{inner}"),
+            Node::Cast(c) => write!(f, "({} as {})", c.value, c.ty.name),
+            Node::UnaryExpr(e) => write!(f, "({}{})", unary_op_str(e.op), e.rhs),
+            Node::BinaryExpr(e) => write!(f, "({} {} {})", e.lhs, op_str(e.op), e.rhs),
+            Node::Let(l) => {
+                write!(f, "new ")?;
+                if l.mutable {
+                    write!(f, "mut ")?;
+                }
+                write!(f, "{}", l.name.name)?;
+                if let Some(ty) = &l.annotation {
+                    write!(f, " :: {}", ty.name)?;
+                }
+                write!(f, " = {}", l.value)
+            }
+            Node::Assign(a) => match a.op {
+                b'=' => write!(f, "{} = {}", a.target.name, a.value),
+                op => write!(f, "{} {}= {}", a.target.name, op as char, a.value),
+            },
+            Node::Function(func) => {
+                let sig = &func.signature;
+                for line in sig.doc.iter().flat_map(|doc| doc.lines()) {
+                    writeln!(f, "/// {line}")?;
+                }
+                write!(f, "func {}(", sig.name.name)?;
+                for (i, param) in sig.params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if param.mutable {
+                        write!(f, "mut ")?;
+                    }
+                    write!(f, "{} :: {}", param.name.name, param.annotation.name)?;
+                }
+                write!(f, ")")?;
+                if let Some(ty) = &sig.returns {
+                    write!(f, " -> {}", ty.name)?;
+                }
+                write_block(f, &func.body)
+            }
+            Node::Call(c) => {
+                write!(f, "{}(", c.callee.name)?;
+                for (i, arg) in c.args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Node::Return(r) => match &r.value {
+                Some(value) => write!(f, "return {value}"),
+                None => write!(f, "return"),
+            },
+            Node::Block(body) => write_block(f, body),
+            Node::If(stmt) => {
+                for (i, branch) in stmt.branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { " elif" };
+                    write!(f, "{keyword} {}", branch.cond)?;
+                    write_block(f, &branch.body)?;
+                }
+                if let Some(body) = &stmt.otherwise {
+                    write!(f, " else")?;
+                    write_block(f, body)?;
+                }
+                Ok(())
+            }
+            Node::While(stmt) => {
+                write!(f, "while {}", stmt.cond)?;
+                write_block(f, &stmt.body)
+            }
+            Node::For(stmt) => {
+                write!(f, "for {} in {}..{}", stmt.var.name, stmt.start, stmt.end)?;
+                write_block(f, &stmt.body)
+            }
+            Node::Break(_) => write!(f, "break"),
+            Node::Continue(_) => write!(f, "continue"),
+        }
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, body: &[Node]) -> fmt::Result {
+    write!(f, " {{")?;
+    for (i, node) in body.iter().enumerate() {
+        if i > 0 {
+            write!(f, ";")?;
+        }
+        write!(f, " {node}")?;
+    }
+    write!(f, " }}")
+}
@@ -1,173 +1,760 @@
-use crate::lexer::token::{Token, TokenKind};
+use std::rc::Rc;
 
-use super::node::{BinaryExpr, Node};
+use crate::{
+    lexer::{
+        lexer::{number_radix, split_number_suffix},
+        token::{Token, TokenKind},
+    },
+    trace,
+};
+
+use super::{
+    error::ParseError,
+    node::{
+        Assign, BinaryExpr, Branch, CallExpr, Cast, ForStmt, FunctionExpr, FunctionSignature,
+        Ident, IfStmt, Let, Node, ParameterExpr, Return, UnaryExpr, WhileStmt, AS_PREC, BINARY_OPS,
+        NOT_PREC, UNARY_PREC,
+    },
+    number::NumType,
+};
 
 // Im lazy :P
 type Tk = TokenKind;
 
+type ParseResult<T> = Result<T, ParseError>;
+
 pub struct Parser<'a> {
-    src: &'a Vec<Token>,
+    src: &'a [Token],
     idx: usize,
-    offset: usize,
+    /// How many `(` we are inside of, newlines don't end anything while this is non-zero
+    nesting: usize,
+    /// How many `{` blocks we are inside of
+    depth: usize,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(src: &'a Vec<Token>) -> Self {
-        // Get the first token
-        let current = src.get(0).unwrap();
-
-        // Return new parser
+    pub fn new(src: &'a [Token]) -> Self {
         Self {
             src,
             idx: 0usize,
-            offset: 0usize,
+            nesting: 0usize,
+            depth: 0usize,
+            errors: Vec::new(),
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Node> {
-        let mut ast: Vec<Node> = Vec::new();
+    /// Parses every statement in the token stream. Errors don't stop the parser, it skips to the
+    /// next statement boundary and keeps going so every error in the file is reported at once.
+    pub fn parse(&mut self) -> Result<Vec<Node>, Vec<ParseError>> {
+        let ast = self.parse_statements();
 
-        'parse: loop {
-            // Check for EOF
-            if self.idx >= self.src.len() - 1 {
-                break 'parse;
+        if self.errors.is_empty() {
+            Ok(ast)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parses statements until the end of the file, or the closing `}` when inside a block
+    fn parse_statements(&mut self) -> Vec<Node> {
+        let mut nodes: Vec<Node> = Vec::new();
+
+        loop {
+            // Skip empty statements
+            while matches!(self.current().kind, Tk::Semicolon | Tk::Newline) {
+                self.idx += 1;
             }
 
-            // Get current token
-            let current = self.src.get(self.idx).unwrap();
+            // Check for the end of the block or file
+            match self.current().kind {
+                Tk::EndOfFile => break,
+                Tk::RCurl if self.depth > 0 => break,
+                _ => {}
+            }
 
-            // Attempt to parse an expression
-            if !current.kind.is_leaf_node() {
-                dbg!(current);
-                match self.parse_expr(current) {
-                    Some(n) => ast.push(n),
-                    None => {},
+            let token = self.current();
+            trace!(
+                Parser,
+                Debug,
+                "statement at token {} {} ({})",
+                self.idx,
+                token.kind,
+                token.span
+            );
+            match self.parse_statement() {
+                Ok(node) => nodes.push(node),
+                Err(e) => {
+                    trace!(
+                        Parser,
+                        Info,
+                        "{e} at token {}, skipping to the next statement",
+                        self.idx
+                    );
+                    self.errors.push(e);
+                    self.synchronize();
                 }
             }
+        }
 
+        nodes
+    }
+
+    /// Parses a single statement and makes sure nothing trails it on the same line
+    fn parse_statement(&mut self) -> ParseResult<Node> {
+        let node = match (&self.current().kind, &self.peek(1).kind) {
+            (Tk::New, _) => self.parse_let()?,
+            (Tk::Func, _) => self.parse_function()?,
+            (Tk::Return, _) => self.parse_return()?,
+            (Tk::If, _) => self.parse_if()?,
+            (Tk::While, _) => self.parse_while()?,
+            (Tk::For, _) => self.parse_for()?,
+            (Tk::LCurl, _) => Node::Block(self.parse_block()?),
+            (Tk::Break, _) => {
+                self.idx += 1;
+                Node::Break(self.previous().span.clone())
+            }
+            (Tk::Continue, _) => {
+                self.idx += 1;
+                Node::Continue(self.previous().span.clone())
+            }
+            (Tk::Ident { .. }, Tk::ColonEqual) => self.parse_short_let()?,
+            (Tk::Ident { .. }, Tk::Equal | Tk::PlusEqual | Tk::MinusEqual) => {
+                self.parse_assign()?
+            }
+            _ => self.parse_expr()?,
+        };
+        self.expect_end_of_statement()?;
+        Ok(node)
+    }
+
+    /// Parses `new [mut] name [:: type] = value`
+    fn parse_let(&mut self) -> ParseResult<Node> {
+        self.idx += 1; // skip `new`
+
+        let mutable = self.current().kind == Tk::Mut;
+        if mutable {
             self.idx += 1;
-            self.idx += self.offset;
-            continue 'parse;
         }
 
-        ast
+        let name = self.parse_ident()?;
+        let annotation = if self.current().kind == Tk::ColonColon {
+            self.idx += 1;
+            Some(self.parse_ident()?)
+        } else {
+            None
+        };
+
+        self.expect(Tk::Equal, "`=`")?;
+        let value = self.parse_expr()?;
+
+        Ok(Node::Let(Let {
+            name,
+            mutable,
+            annotation,
+            value: Box::new(value),
+        }))
+    }
+
+    /// Parses `name := value`, which declares an immutable variable without an annotation
+    fn parse_short_let(&mut self) -> ParseResult<Node> {
+        let name = self.parse_ident()?;
+        self.idx += 1; // skip `:=`
+        let value = self.parse_expr()?;
+
+        Ok(Node::Let(Let {
+            name,
+            mutable: false,
+            annotation: None,
+            value: Box::new(value),
+        }))
+    }
+
+    /// Parses `name = value`, `name += value` and `name -= value`
+    fn parse_assign(&mut self) -> ParseResult<Node> {
+        let target = self.parse_ident()?;
+
+        let token = self.current();
+        let op = match token.kind {
+            Tk::PlusEqual => b'+',
+            Tk::MinusEqual => b'-',
+            _ => b'=',
+        };
+        self.idx += 1;
+        let value = self.parse_expr()?;
+
+        Ok(Node::Assign(Assign {
+            target,
+            op,
+            value: Box::new(value),
+            span: token.span.clone(),
+        }))
     }
 
-    fn parse_expr(&mut self, token: &Token) -> Option<Node> {
+    fn parse_ident(&mut self) -> ParseResult<Ident> {
+        let token = self.current();
         match &token.kind {
-            Tk::Number { value: v } => self.parse_number(v),
+            Tk::Ident { value } => {
+                self.idx += 1;
+                Ok(Ident {
+                    name: value.clone(),
+                    span: token.span.clone(),
+                })
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
 
-            // Arithmetic
-            Tk::Plus => self.parse_binary_expr(token, None),
-            Tk::Minus => self.parse_binary_expr(token, None),
-            Tk::Star => self.parse_binary_expr(token, None),
-            Tk::Slash => self.parse_binary_expr(token, None),
-            Tk::Modulo => self.parse_binary_expr(token, None),
-            Tk::Caret => self.parse_binary_expr(token, None),
-            _ => panic!("Unexpected token"),
+    fn expect_end_of_statement(&mut self) -> ParseResult<()> {
+        match self.current().kind {
+            Tk::Semicolon | Tk::Newline => {
+                self.idx += 1;
+                Ok(())
+            }
+            Tk::RCurl | Tk::EndOfFile => Ok(()),
+            _ => Err(self.unexpected("end of statement")),
         }
     }
 
-    fn parse_number(&mut self, val: &String) -> Option<Node> {
-        if val.contains(".") {
-            // Indicates a floating point number
-            match val.parse::<f32>() {
-                Ok(v) => Some(Node::Number(v)),
-                Err(_) => panic!("Error parsing number"),
+    /// Skips tokens until the end of the statement that caused an error. A `}` is left alone
+    /// inside of a block so the block can still be closed, any blocks opened by the broken
+    /// statement itself are skipped as a whole.
+    fn synchronize(&mut self) {
+        self.nesting = 0;
+        let mut braces = 0usize;
+        loop {
+            match self.current().kind {
+                Tk::LCurl => {
+                    braces += 1;
+                    self.idx += 1;
+                }
+                Tk::RCurl if braces > 0 => {
+                    braces -= 1;
+                    self.idx += 1;
+                }
+                Tk::Semicolon | Tk::Newline if braces > 0 => self.idx += 1,
+                Tk::Semicolon | Tk::Newline => {
+                    self.idx += 1;
+                    return;
+                }
+                Tk::RCurl if self.depth == 0 => {
+                    self.idx += 1;
+                    return;
+                }
+                Tk::RCurl | Tk::EndOfFile => return,
+                _ => self.idx += 1,
             }
-        } else {
-            // Indicates an integer number
-            match val.parse::<i32>() {
-                Ok(v) => Some(Node::Integer(v)),
-                Err(_) => panic!("Error parsing number"),
+        }
+    }
+
+    /// Parses `{ statements }`
+    fn parse_block(&mut self) -> ParseResult<Vec<Node>> {
+        let open = self.expect(Tk::LCurl, "`{`")?;
+        self.depth += 1;
+        let body = self.parse_statements();
+        self.depth -= 1;
+
+        let close = self.current();
+        if close.kind != Tk::RCurl {
+            return Err(ParseError::UnclosedDelimiter {
+                open: Box::new(open.clone()),
+                found: close.clone(),
+            });
+        }
+        self.idx += 1;
+
+        Ok(body)
+    }
+
+    /// Parses `if cond { } elif cond { } else { }`, the `elif` and `else` may start on a new line
+    fn parse_if(&mut self) -> ParseResult<Node> {
+        let mut branches = vec![self.parse_branch()?];
+        let mut otherwise = None;
+
+        loop {
+            // Look past newlines for a continuation of the chain, put them back if there isn't one
+            let before = self.idx;
+            self.skip_newlines();
+
+            match self.current().kind {
+                Tk::Elif => branches.push(self.parse_branch()?),
+                Tk::Else => {
+                    self.idx += 1;
+                    self.skip_newlines();
+                    otherwise = Some(self.parse_block()?);
+                    break;
+                }
+                _ => {
+                    self.idx = before;
+                    break;
+                }
             }
         }
+
+        Ok(Node::If(IfStmt {
+            branches,
+            otherwise,
+        }))
+    }
+
+    /// Parses `keyword cond { body }`
+    fn parse_branch(&mut self) -> ParseResult<Branch> {
+        let keyword = self.current();
+        self.idx += 1;
+
+        let cond = self.parse_expr()?;
+        self.skip_newlines();
+        let body = self.parse_block()?;
+
+        Ok(Branch {
+            cond,
+            body,
+            span: keyword.span.clone(),
+        })
+    }
+
+    /// Parses `while cond { body }`
+    fn parse_while(&mut self) -> ParseResult<Node> {
+        let Branch { cond, body, span } = self.parse_branch()?;
+
+        Ok(Node::While(WhileStmt {
+            cond: Box::new(cond),
+            body,
+            span,
+        }))
+    }
+
+    /// Parses `for var in start..end { body }`
+    fn parse_for(&mut self) -> ParseResult<Node> {
+        self.idx += 1; // skip `for`
+        let var = self.parse_ident()?;
+        self.expect(Tk::In, "`in`")?;
+
+        let start = self.parse_expr()?;
+        let range = self.expect(Tk::DotDot, "`..`")?;
+        let first = self.current();
+        let end = self.parse_expr()?;
+        let end_span = first.span.to(&self.previous().span);
+
+        self.skip_newlines();
+        let body = self.parse_block()?;
+
+        Ok(Node::For(ForStmt {
+            var,
+            start: Box::new(start),
+            end: Box::new(end),
+            end_span,
+            body,
+            span: range.span.clone(),
+        }))
     }
 
-    fn parse_binary_expr(&mut self, token: &Token, maybe_lhs: Option<Node>) -> Option<Node> {
-        // Get operator and precedence
-        let (op, prec) = token.kind.binary_operator();
-        if op == 0 { panic!("Invalid token for BinaryExpr") }
-        
-        // ----------------- GET CHILD NODES ----------------- \\ 
-        
-        // Get LHS node
-        let lhs: Node;
+    /// Parses `func name(params) [-> type] { body }`
+    fn parse_function(&mut self) -> ParseResult<Node> {
+        let doc = self.current().doc();
+        self.idx += 1; // skip `func`
+        let name = self.parse_ident()?;
+
+        let open = self.expect(Tk::LPar, "`(`")?;
+        let (params, _) = self.parse_list(open, Self::parse_param)?;
 
-        if maybe_lhs.is_some() {
-            lhs = maybe_lhs.unwrap();
+        let returns = if self.current().kind == Tk::RArrow {
+            self.idx += 1;
+            Some(self.parse_ident()?)
         } else {
-            // Get LHS token
-            self.idx -= 1;
-            let lhs_token = self.src.get(self.idx).unwrap_or_else(|| {
-                panic!("Missing LHS token");
-            });
+            None
+        };
 
-            lhs = self.parse_expr(lhs_token).unwrap_or_else(|| {
-                panic!("LHS node is invalid");
-            });
+        // The body is allowed to start on the next line
+        self.skip_newlines();
+        let body = self.parse_block()?;
+
+        Ok(Node::Function(Rc::new(FunctionExpr {
+            signature: FunctionSignature {
+                name,
+                params,
+                returns,
+                doc,
+            },
+            body,
+        })))
+    }
+
+    /// Parses `[mut] name :: type`
+    fn parse_param(&mut self) -> ParseResult<ParameterExpr> {
+        let mutable = self.current().kind == Tk::Mut;
+        if mutable {
+            self.idx += 1;
         }
 
-        // Get RHS token
-        self.idx += 2;
-        let rhs_token = self.src.get(self.idx).unwrap_or_else(|| {
-            panic!("Missing RHS token");
-        });
-        
-        // Get RHS node
-        let rhs = self.parse_expr(rhs_token).unwrap_or_else(|| {
-            panic!("RHS node is invalid");
-        });
-
-        // ----------------- LOOKAHEAD ----------------- \\ 
-
-        // Look ahead for possible other binary expressions
-        match self.src.get(self.idx + 1) {
-            Some(t) => {
-                // If the lookahead is a binary operator...
-                if t.kind.binary_operator().0 != 0 {
-                    
-                    // Get inner BinaryExpr
-                    let inner_expr = self.parse_binary_expr(t, Some(rhs)).unwrap_or_else(|| {
-                        panic!("Error parsing inner BinaryExpr");
-                    });
-
-                    return Some(Node::BinaryExpr(BinaryExpr {
-                        lhs: Box::new(lhs),
-                        rhs: Box::new(inner_expr),
-                        op,
-                        prec,
-                    }));
-                }
+        let name = self.parse_ident()?;
+        self.expect(Tk::ColonColon, "`::`")?;
+        let annotation = self.parse_ident()?;
+
+        Ok(ParameterExpr {
+            name,
+            annotation,
+            mutable,
+        })
+    }
+
+    /// Parses `return [value]`
+    fn parse_return(&mut self) -> ParseResult<Node> {
+        let token = self.current();
+        self.idx += 1;
+
+        let value = match self.current().kind {
+            Tk::Newline | Tk::Semicolon | Tk::RCurl | Tk::EndOfFile => None,
+            _ => Some(Box::new(self.parse_expr()?)),
+        };
+
+        Ok(Node::Return(Return {
+            value,
+            span: token.span.clone(),
+        }))
+    }
+
+    /// Parses a comma separated list after `open` up to the closing `)`, returning the items and
+    /// the closing token. Newlines are allowed anywhere in the list.
+    fn parse_list<T>(
+        &mut self,
+        open: &'a Token,
+        mut item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<(Vec<T>, &'a Token)> {
+        let mut items = Vec::new();
+        self.nesting += 1;
+
+        loop {
+            self.skip_newlines();
+            if self.current().kind == Tk::RPar {
+                break;
             }
 
-            None => {}
+            items.push(item(self)?);
+
+            self.skip_newlines();
+            match self.current().kind {
+                Tk::Comma => self.idx += 1,
+                Tk::RPar => break,
+                _ => {
+                    return Err(ParseError::UnclosedDelimiter {
+                        open: Box::new(open.clone()),
+                        found: self.current().clone(),
+                    })
+                }
+            }
         }
 
-        // ----------------- CREATE NODE ----------------- \\ 
+        let close = self.current();
+        self.idx += 1;
+        self.nesting -= 1;
 
-        return Some(Node::BinaryExpr(BinaryExpr {
-            lhs: Box::new(lhs),
-            rhs: Box::new(rhs),
-            op,
-            prec,
-        }));
+        Ok((items, close))
     }
 
-    /// Advances self.idx until it comes across a token that isn't a leaf node
-    fn next_non_leaf(&mut self) -> Option<&'a Token> {
+    /// Parses a full expression, consuming binary operators of any precedence
+    fn parse_expr(&mut self) -> ParseResult<Node> {
+        self.parse_binary(u8::MAX)
+    }
+
+    /// Precedence climbing: parses an operand and then keeps folding binary operators into the LHS
+    /// for as long as they bind tighter than `limit`
+    fn parse_binary(&mut self, limit: u8) -> ParseResult<Node> {
+        let mut lhs = self.parse_prefix()?;
+
         loop {
+            if self.nesting > 0 {
+                self.skip_newlines();
+            }
+
+            let token = self.current();
+            if token.kind == Tk::As && AS_PREC < limit {
+                self.idx += 1;
+                lhs = Node::Cast(Cast {
+                    value: Box::new(lhs),
+                    ty: self.parse_ident()?,
+                    span: token.span.clone(),
+                });
+                continue;
+            }
+
+            let (op, prec) = token.kind.binary_operator();
+            if !BINARY_OPS.contains(&op) || prec >= limit {
+                break;
+            }
             self.idx += 1;
-            match self.src.get(self.idx) {
-                Some(t) => {
-                    if !t.kind.is_leaf_node() {
-                        return Some(t);
-                    } else {
-                        continue;
-                    }
+            self.skip_newlines();
+
+            // Right associative operators may absorb operators of the same precedence into the RHS
+            let rhs = if token.kind.is_right_assoc() {
+                self.parse_binary(prec + 1)?
+            } else {
+                self.parse_binary(prec)?
+            };
+
+            lhs = Node::BinaryExpr(BinaryExpr {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span: token.span.clone(),
+            });
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parses prefix operators and then falls through to a primary expression
+    fn parse_prefix(&mut self) -> ParseResult<Node> {
+        let token = self.current();
+        trace!(
+            Parser,
+            Trace,
+            "operand at token {} {} ({})",
+            self.idx,
+            token.kind,
+            token.span
+        );
+        // Only reached where an operand is expected, so `-`, `*` and `&` can't be binary here
+        let (op, prec) = match token.kind {
+            Tk::Minus => (b'-', UNARY_PREC),
+            Tk::Bang => (b'!', UNARY_PREC),
+            Tk::Hash => (b'#', UNARY_PREC),
+            Tk::Ampersand => (b'&', UNARY_PREC),
+            Tk::Star => (b'*', UNARY_PREC),
+            Tk::Not => (b'!', NOT_PREC),
+            _ => return self.parse_primary(),
+        };
+
+        // The most negative integer of a type only fits once negated, like `-128i8`, so `-` and
+        // the number are read as one literal. `^` binds tighter than `-` and is left alone.
+        if let (b'-', Tk::Number { value }) = (op, &self.peek(1).kind) {
+            let number = self.peek(1);
+            if self.peek(2).kind != Tk::Caret && self.parse_number(number, value, false).is_err() {
+                if let Ok(node) = self.parse_number(number, value, true) {
+                    self.idx += 2;
+                    return Ok(node);
                 }
-                None => return None,
             }
         }
+
+        self.idx += 1;
+        let rhs = self.parse_binary(prec)?;
+        Ok(Node::UnaryExpr(UnaryExpr {
+            op,
+            rhs: Box::new(rhs),
+            span: token.span.clone(),
+        }))
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Node> {
+        let token = self.current();
+        let node = match &token.kind {
+            Tk::Number { value } => self.parse_number(token, value, false)?,
+            Tk::Literal { value } => Node::Str(value.clone()),
+            Tk::True => Node::Bool(true),
+            Tk::False => Node::Bool(false),
+            Tk::Ident { .. } if self.peek(1).kind == Tk::LPar => return self.parse_call(),
+            Tk::Ident { .. } => return self.parse_ident().map(Node::Ident),
+            Tk::LPar => return self.parse_group(),
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.idx += 1;
+        Ok(node)
+    }
+
+    /// Parses `name(args)`
+    fn parse_call(&mut self) -> ParseResult<Node> {
+        let callee = self.parse_ident()?;
+        let open = self.current();
+        self.idx += 1;
+        let (args, close) = self.parse_list(open, Self::parse_expr)?;
+
+        Ok(Node::Call(CallExpr {
+            span: callee.span.to(&close.span),
+            callee,
+            args,
+        }))
+    }
+
+    /// Parses `( expr )`, the inner expression starts again from the loosest precedence
+    fn parse_group(&mut self) -> ParseResult<Node> {
+        let open = self.current();
+        self.idx += 1;
+        self.nesting += 1;
+        self.skip_newlines();
+        let inner = self.parse_expr()?;
+
+        let close = self.current();
+        if close.kind != Tk::RPar {
+            return Err(ParseError::UnclosedDelimiter {
+                open: Box::new(open.clone()),
+                found: close.clone(),
+            });
+        }
+        self.idx += 1;
+        self.nesting -= 1;
+
+        Ok(Node::Group(Box::new(inner)))
     }
-}
\ No newline at end of file
+
+    /// Converts a number literal to a value, the lexer has already checked it is well formed so
+    /// the only thing that can go wrong is the value not fitting in its type
+    /// Parses a number token, as its negation when `negate` is set
+    fn parse_number(&self, token: &Token, val: &str, negate: bool) -> ParseResult<Node> {
+        let text = val.replace('_', "");
+        let radix = number_radix(text.as_bytes());
+        let prefix = if radix == 10 { 0 } else { 2 };
+        let (digits, suffix) = split_number_suffix(&text[prefix..], radix);
+
+        let is_float = suffix.starts_with('f') || (radix == 10 && digits.contains(['.', 'e', 'E']));
+        let ty = match NumType::from_name(suffix) {
+            Some(ty) => ty,
+            None if is_float => NumType::F64,
+            None => NumType::I64,
+        };
+        let out_of_range = || ParseError::InvalidNumber {
+            token: token.clone(),
+            ty: ty.name(),
+        };
+
+        if is_float {
+            let value = digits.parse::<f64>().map_err(|_| out_of_range())?;
+            if !ty.round(value).is_finite() {
+                return Err(out_of_range());
+            }
+            return Ok(Node::Number(ty.round(value), ty));
+        }
+
+        // Anything too big for a `u64` is too big for every type
+        u64::from_str_radix(digits, radix)
+            .ok()
+            .map(|value| {
+                if negate {
+                    -i128::from(value)
+                } else {
+                    value.into()
+                }
+            })
+            .and_then(|value| ty.fit(value))
+            .map(|value| Node::Integer(value, ty))
+            .ok_or_else(out_of_range)
+    }
+
+    /// Consumes the current token if it is `kind`, otherwise errors with `expected`
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> ParseResult<&'a Token> {
+        let token = self.current();
+        if token.kind == kind {
+            self.idx += 1;
+            Ok(token)
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.current().kind == Tk::Newline {
+            self.idx += 1;
+        }
+    }
+
+    /// Builds an error for the token under the cursor
+    fn unexpected(&self, expected: &str) -> ParseError {
+        ParseError::UnexpectedToken {
+            token: self.current().clone(),
+            expected: expected.to_string(),
+        }
+    }
+
+    /// Returns the token under the cursor, the lexer always terminates the stream with `EndOfFile`
+    fn current(&self) -> &'a Token {
+        self.peek(0)
+    }
+
+    /// The token right before the cursor
+    fn previous(&self) -> &'a Token {
+        &self.src[self.idx.saturating_sub(1)]
+    }
+
+    /// Looks `n` tokens past the cursor, anything past the end is `EndOfFile`
+    fn peek(&self, n: usize) -> &'a Token {
+        &self.src[(self.idx + n).min(self.src.len() - 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lexer::Lexer;
+
+    fn errors(src: &str) -> Vec<ParseError> {
+        let mut lexer = Lexer::new(src);
+        let tokens = lexer.scan().expect("source lexes").clone();
+        Parser::new(&tokens).parse().expect_err("source has errors")
+    }
+
+    fn printed(src: &str) -> Vec<String> {
+        let (_, ast) = crate::ast::parse(src);
+        ast.iter().map(|node| node.to_string()).collect()
+    }
+
+    #[test]
+    fn binds_operators_by_precedence() {
+        let src =
+            "-2 ^ 2\n2 ^ 3 ^ 2\na as i8 * b\nnot a == b and true\n1 + 2 * 3 < 7 or false\n-a as u8";
+        assert_eq!(
+            printed(src),
+            [
+                "(-(2 ^ 2))",
+                "(2 ^ (3 ^ 2))",
+                "((a as i8) * b)",
+                "((!(a == b)) and true)",
+                "(((1 + (2 * 3)) < 7) or false)",
+                "((-a) as u8)",
+            ]
+        );
+    }
+
+    #[test]
+    fn types_number_literals_by_suffix() {
+        let (_, ast) = crate::ast::parse("255u8\n1_000\n0xFF\n1.5f32\n2e3");
+        assert!(matches!(ast[0], Node::Integer(255, NumType::U8)));
+        assert!(matches!(ast[1], Node::Integer(1000, NumType::I64)));
+        assert!(matches!(ast[2], Node::Integer(255, NumType::I64)));
+        assert!(matches!(ast[3], Node::Number(v, NumType::F32) if v == 1.5));
+        assert!(matches!(ast[4], Node::Number(v, NumType::F64) if v == 2000.0));
+
+        // Negative literals stay negations, unless only the negation fits
+        let src = "-128i8\n-9223372036854775808\n-0x80i8 as i64\n-127i8";
+        assert_eq!(
+            printed(src),
+            ["-128", "-9223372036854775808", "(-128 as i64)", "(-127)"]
+        );
+
+        let found = errors("256u8\n0x1_0000i16\n-129i8\n-128i8 ^ 1");
+        assert!(matches!(
+            &found[..],
+            [
+                ParseError::InvalidNumber { ty: "u8", .. },
+                ParseError::InvalidNumber { ty: "i16", .. },
+                ParseError::InvalidNumber { ty: "i8", .. },
+                ParseError::InvalidNumber { ty: "i8", .. },
+            ]
+        ));
+        assert_eq!(found[0].token().kind.value(), Some("256u8"));
+    }
+
+    #[test]
+    fn recovers_to_report_every_statement() {
+        let src = "new x = 1 +\nnew = 2\nf(1, 2\nnew y = )\nnew z = 3";
+        let found = errors(src);
+        assert_eq!(found.len(), 2, "{found:?}");
+        assert!(
+            matches!(&found[0], ParseError::UnexpectedToken { token, .. }
+            if token.kind.name() == "New" && found[0].span().start() == src.find("new =").unwrap())
+        );
+        assert!(
+            matches!(&found[1], ParseError::UnclosedDelimiter { open, .. }
+            if open.span.start() == src.find('(').unwrap())
+        );
+    }
+}
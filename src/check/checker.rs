@@ -0,0 +1,223 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        node::{CallExpr, FunctionExpr, Ident, Node},
+        number::NumType,
+    },
+    lexer::token::Span,
+    resolve::resolver::Bindings,
+};
+
+use super::error::CheckError;
+
+/// What the checker remembers about a declaration
+#[derive(Clone)]
+struct Binding {
+    mutable: bool,
+    /// Number of parameters if the binding is a function
+    arity: Option<usize>,
+}
+
+/// Static checks that run on the AST before it is evaluated:
+///
+/// - only variables declared with `mut` are assigned to after their declaration
+/// - functions are called with the number of arguments they declare
+/// - `return` only appears inside of a function
+/// - `break` and `continue` only appear inside of a loop
+///
+/// Bodies and declarations are handled the way `Resolver` describes.
+#[derive(Clone)]
+pub struct Checker {
+    /// Every declaration seen so far, keyed by where it was declared
+    declarations: HashMap<Span, Binding>,
+    /// What every name used so far refers to
+    bindings: Bindings,
+    deferred: Vec<Rc<FunctionExpr>>,
+    /// How many function bodies we are inside of
+    functions: usize,
+    /// How many loops we are inside of, reset when entering a function
+    loops: usize,
+    errors: Vec<CheckError>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            declarations: HashMap::new(),
+            bindings: HashMap::new(),
+            deferred: Vec::new(),
+            functions: 0usize,
+            loops: 0usize,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Checks `ast` using the bindings the resolver found for it
+    pub fn check(&mut self, ast: &[Node], bindings: &Bindings) -> Result<(), Vec<CheckError>> {
+        self.bindings
+            .extend(bindings.iter().map(|(k, v)| (k.clone(), v.clone())));
+        for node in ast {
+            self.visit(node);
+        }
+        while let Some(func) = self.deferred.pop() {
+            self.visit_body(&func);
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Let(l) => {
+                self.visit(&l.value);
+                self.declare(&l.name.span, l.mutable, None);
+            }
+            Node::Assign(a) => {
+                self.visit(&a.value);
+
+                // Unknown names are left for the resolver to report
+                if let Some((declared, binding)) = self.lookup(&a.target) {
+                    if !binding.mutable {
+                        self.errors.push(CheckError::AssignToImmutable {
+                            name: a.target.name.clone(),
+                            span: a.target.span.clone(),
+                            declared: declared.clone(),
+                        });
+                    }
+                }
+            }
+            Node::Function(func) => {
+                let sig = &func.signature;
+                self.declare(&sig.name.span, false, Some(sig.params.len()));
+                self.deferred.push(Rc::clone(func));
+            }
+            Node::Call(c) => self.visit_call(c),
+            Node::Return(r) => {
+                if self.functions == 0 {
+                    self.errors.push(CheckError::ReturnOutsideFunction {
+                        span: r.span.clone(),
+                    });
+                }
+                if let Some(value) = &r.value {
+                    self.visit(value);
+                }
+            }
+            Node::Block(body) => self.visit_block(body),
+            Node::If(stmt) => {
+                for branch in &stmt.branches {
+                    self.visit(&branch.cond);
+                    self.visit_block(&branch.body);
+                }
+                if let Some(body) = &stmt.otherwise {
+                    self.visit_block(body);
+                }
+            }
+            Node::While(stmt) => {
+                self.visit(&stmt.cond);
+                self.loops += 1;
+                self.visit_block(&stmt.body);
+                self.loops -= 1;
+            }
+            Node::For(stmt) => {
+                self.visit(&stmt.start);
+                self.visit(&stmt.end);
+                self.declare(&stmt.var.span, false, None);
+                self.loops += 1;
+                self.visit_block(&stmt.body);
+                self.loops -= 1;
+            }
+            Node::Break(span) => self.check_in_loop("break", span),
+            Node::Continue(span) => self.check_in_loop("continue", span),
+            Node::Group(inner) => self.visit(inner),
+            Node::UnaryExpr(e) => self.visit(&e.rhs),
+            Node::BinaryExpr(e) => {
+                self.visit(&e.lhs);
+                self.visit(&e.rhs);
+            }
+            Node::Cast(c) => {
+                self.visit(&c.value);
+                if NumType::from_name(&c.ty.name).is_none() {
+                    self.errors.push(CheckError::InvalidCastType {
+                        name: c.ty.name.clone(),
+                        span: c.ty.span.clone(),
+                    });
+                }
+            }
+            Node::Integer(..)
+            | Node::Number(..)
+            | Node::Bool(_)
+            | Node::Str(_)
+            | Node::Ident(_) => {}
+        }
+    }
+
+    /// Bodies are checked once everything around them has been, so no loop encloses them
+    fn visit_body(&mut self, func: &FunctionExpr) {
+        self.functions += 1;
+        for param in &func.signature.params {
+            self.declare(&param.name.span, param.mutable, None);
+        }
+        for node in &func.body {
+            self.visit(node);
+        }
+        self.functions -= 1;
+    }
+
+    fn visit_block(&mut self, body: &[Node]) {
+        for node in body {
+            self.visit(node);
+        }
+    }
+
+    fn check_in_loop(&mut self, keyword: &'static str, span: &Span) {
+        if self.loops == 0 {
+            self.errors.push(CheckError::OutsideLoop {
+                keyword,
+                span: span.clone(),
+            });
+        }
+    }
+
+    fn visit_call(&mut self, c: &CallExpr) {
+        for arg in &c.args {
+            self.visit(arg);
+        }
+
+        let Some((declared, binding)) = self.lookup(&c.callee) else {
+            return;
+        };
+        let error = match binding.arity {
+            None => CheckError::NotCallable {
+                name: c.callee.name.clone(),
+                span: c.callee.span.clone(),
+                declared: declared.clone(),
+            },
+            Some(arity) if arity != c.args.len() => CheckError::ArityMismatch {
+                name: c.callee.name.clone(),
+                expected: arity,
+                found: c.args.len(),
+                span: c.span.clone(),
+                declared: declared.clone(),
+            },
+            Some(_) => return,
+        };
+        self.errors.push(error);
+    }
+
+    fn declare(&mut self, span: &Span, mutable: bool, arity: Option<usize>) {
+        self.declarations
+            .insert(span.clone(), Binding { mutable, arity });
+    }
+
+    /// The declaration a name refers to along with where it is, unknown names are left for the
+    /// resolver to report
+    fn lookup(&self, name: &Ident) -> Option<(&Span, &Binding)> {
+        let declared = self.bindings.get(&name.span)?;
+        self.declarations.get_key_value(declared)
+    }
+}
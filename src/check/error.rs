@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::{diagnostics::diagnostic::Diagnostic, lexer::token::Span};
+
+/// Errors found by the static checks that run between parsing and evaluation
+#[derive(Debug, Clone)]
+pub enum CheckError {
+    /// Assigning to a variable that wasn't declared with `mut`
+    AssignToImmutable {
+        name: String,
+        span: Span,
+        declared: Span,
+    },
+
+    /// Calling a function with the wrong number of arguments
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+        declared: Span,
+    },
+
+    /// Calling something that isn't a function
+    NotCallable {
+        name: String,
+        span: Span,
+        declared: Span,
+    },
+
+    /// `return` at the top level of a file
+    ReturnOutsideFunction { span: Span },
+
+    /// `break` or `continue` that isn't inside of a loop
+    OutsideLoop { keyword: &'static str, span: Span },
+
+    /// `as` followed by something that isn't a number type
+    InvalidCastType { name: String, span: Span },
+}
+
+impl CheckError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::AssignToImmutable { span, .. } => span,
+            Self::ArityMismatch { span, .. } => span,
+            Self::NotCallable { span, .. } => span,
+            Self::ReturnOutsideFunction { span } => span,
+            Self::OutsideLoop { span, .. } => span,
+            Self::InvalidCastType { span, .. } => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::AssignToImmutable { name, declared, .. } => diag
+                .with_label("cannot assign twice to an immutable variable")
+                .with_secondary(declared.clone(), "declared here")
+                .with_note(format!("declare `{name}` with `mut` to allow this")),
+            Self::ArityMismatch {
+                expected, declared, ..
+            } => diag
+                .with_label(format!("expected {}", plural(*expected, "argument")))
+                .with_secondary(declared.clone(), "function defined here"),
+            Self::NotCallable { declared, .. } => diag
+                .with_label("not a function")
+                .with_secondary(declared.clone(), "declared here"),
+            Self::ReturnOutsideFunction { .. } => diag.with_label("not inside a function"),
+            Self::OutsideLoop { .. } => diag.with_label("not inside a loop"),
+            Self::InvalidCastType { .. } => diag
+                .with_label("not a number type")
+                .with_note("numbers can be cast to `i8`..`i64`, `u8`..`u64`, `f32` and `f64`"),
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AssignToImmutable { name, .. } => {
+                write!(f, "cannot assign to immutable variable `{name}`")
+            }
+            Self::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "function `{name}` takes {} but {} supplied",
+                plural(*expected, "argument"),
+                plural(*found, "argument")
+            ),
+            Self::NotCallable { name, .. } => write!(f, "`{name}` is not a function"),
+            Self::ReturnOutsideFunction { .. } => write!(f, "`return` outside of a function"),
+            Self::OutsideLoop { keyword, .. } => write!(f, "`{keyword}` outside of a loop"),
+            Self::InvalidCastType { name, .. } => write!(f, "cannot cast to `{name}`"),
+        }
+    }
+}
+
+/// Formats `n` followed by `noun`, adding an `s` unless `n` is one
+fn plural(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{n} {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
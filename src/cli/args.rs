@@ -0,0 +1,248 @@
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+};
+
+use crate::trace::tracer::Filter;
+
+pub const USAGE: &str = "\
+usage: starkey [command] [options] [file]
+
+Starts an interactive session when no file is given.
+
+commands:
+  run       run the file, this is the default
+  check     report problems in the file without running it
+  tokens    print the tokens the file is lexed into
+  ast       print the statements the file is parsed into
+  fmt       format the file
+  build     compile the file and print its bytecode
+  lsp       start a language server speaking JSON-RPC on stdin and stdout
+
+options:
+  --format <text|debug|json|sexpr>
+                                 how `tokens` and `ast` print their output
+  --colour <auto|always|never>   whether diagnostics are coloured
+  --interpret                    run by walking the AST instead of running bytecode
+  --check                        make `fmt` fail on unformatted files instead of rewriting them
+  --trace <filter>               print what the lexer, parser and evaluator are doing, e.g.
+                                 `debug` or `parser=trace,eval=info`, also read from
+                                 STARKEY_TRACE
+  --help                         show this message
+
+exit codes:
+  1 runtime error, 2 usage error, 3 lex error, 4 parse error, 5 type error, 6 unreadable file,
+  7 program too large to compile, 8 file not formatted";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Check,
+    Tokens,
+    Ast,
+    Fmt,
+    Build,
+    Lsp,
+    Help,
+}
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "run" => Some(Self::Run),
+            "check" => Some(Self::Check),
+            "tokens" => Some(Self::Tokens),
+            "ast" => Some(Self::Ast),
+            "fmt" => Some(Self::Fmt),
+            "build" => Some(Self::Build),
+            "lsp" => Some(Self::Lsp),
+            "help" => Some(Self::Help),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Run => "run",
+            Self::Check => "check",
+            Self::Tokens => "tokens",
+            Self::Ast => "ast",
+            Self::Fmt => "fmt",
+            Self::Build => "build",
+            Self::Lsp => "lsp",
+            Self::Help => "help",
+        }
+    }
+}
+
+/// How `tokens` and `ast` print what they produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The way it would be written in source, one item per line
+    Text,
+    /// Rust's pretty printed debug output, showing every field
+    Debug,
+    /// A JSON array for other programs to read, see `serialize::tree` for its shape
+    Json,
+    /// The same data as `Json` written as an S-expression
+    Sexpr,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "debug" => Some(Self::Debug),
+            "json" => Some(Self::Json),
+            "sexpr" => Some(Self::Sexpr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    /// Colour diagnostics when stderr is a terminal and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl Colour {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        match self {
+            Self::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Problems with the command line
+#[derive(Debug, Clone)]
+pub enum ArgError {
+    UnknownFlag(String),
+    /// A flag that takes a value was the last argument
+    MissingValue(&'static str),
+    InvalidValue {
+        flag: &'static str,
+        value: String,
+    },
+    /// More than one file was given
+    UnexpectedArgument(String),
+    /// A command that works on a file was given without one
+    MissingPath(Command),
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFlag(flag) => write!(f, "unknown option `{flag}`"),
+            Self::MissingValue(flag) => write!(f, "`{flag}` needs a value"),
+            Self::InvalidValue { flag, value } => {
+                write!(f, "`{value}` is not a valid value for `{flag}`")
+            }
+            Self::UnexpectedArgument(arg) => write!(f, "unexpected argument `{arg}`"),
+            Self::MissingPath(command) => write!(f, "`{}` needs a file", command.name()),
+        }
+    }
+}
+
+/// The parsed command line
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    /// `None` starts an interactive session
+    pub path: Option<String>,
+    pub format: Format,
+    pub colour: Colour,
+    pub interpret: bool,
+    /// `fmt` only reports whether the file is formatted
+    pub check: bool,
+    /// `None` leaves tracing to `STARKEY_TRACE`
+    pub trace: Option<Filter>,
+}
+
+impl Args {
+    /// Parses the arguments after the binary's name. A first argument that isn't a command is
+    /// taken as the file to run, so `starkey main.sk` is the same as `starkey run main.sk`.
+    pub fn parse(args: &[String]) -> Result<Self, ArgError> {
+        let mut parsed = Self {
+            command: Command::Run,
+            path: None,
+            format: Format::Text,
+            colour: Colour::Auto,
+            interpret: false,
+            check: false,
+            trace: None,
+        };
+
+        let mut args = args.iter();
+        let mut command_given = false;
+        while let Some(arg) = args.next() {
+            // Values can be given as `--flag value` or `--flag=value`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = |flag: &'static str| {
+                inline
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or(ArgError::MissingValue(flag))
+            };
+
+            match flag {
+                "--format" => {
+                    let v = value("--format")?;
+                    parsed.format = Format::from_name(&v).ok_or(ArgError::InvalidValue {
+                        flag: "--format",
+                        value: v,
+                    })?;
+                }
+                "--colour" => {
+                    let v = value("--colour")?;
+                    parsed.colour = Colour::from_name(&v).ok_or(ArgError::InvalidValue {
+                        flag: "--colour",
+                        value: v,
+                    })?;
+                }
+                "--trace" => {
+                    let v = value("--trace")?;
+                    parsed.trace = Some(Filter::parse(&v).map_err(|_| ArgError::InvalidValue {
+                        flag: "--trace",
+                        value: v,
+                    })?);
+                }
+                "--interpret" => parsed.interpret = true,
+                "--check" => parsed.check = true,
+                "--help" | "-h" => parsed.command = Command::Help,
+                _ if flag.starts_with('-') => return Err(ArgError::UnknownFlag(arg.clone())),
+                _ => match Command::from_name(arg) {
+                    Some(command) if !command_given && parsed.path.is_none() => {
+                        parsed.command = command;
+                        command_given = true;
+                    }
+                    _ if parsed.path.is_none() => parsed.path = Some(arg.clone()),
+                    _ => return Err(ArgError::UnexpectedArgument(arg.clone())),
+                },
+            }
+        }
+
+        if parsed.path.is_none()
+            && !matches!(parsed.command, Command::Run | Command::Lsp | Command::Help)
+        {
+            return Err(ArgError::MissingPath(parsed.command));
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{fs, rc::Rc};
+
+use crate::{
+    ast::{node::Node, parser::Parser},
+    check::checker::Checker,
+    compile::{chunk::Function, compiler::Compiler, disassembler::disassemble, globals::Globals},
+    diagnostics::{
+        diagnostic::{Diagnostic, Severity},
+        renderer::Renderer,
+    },
+    eval::{interpreter::Interpreter, value::Value},
+    format::formatter::{same_program, Formatter},
+    lexer::{error::LexError, lexer::Lexer, source::SourceFile, token::Token},
+    resolve::resolver::Resolver,
+    typeck::infer::{TypeChecker, Types},
+    vm::machine::Vm,
+};
+
+use super::{
+    args::{Args, Command},
+    exit::Failure,
+    output,
+};
+
+/// Runs the command in `args` on the file at `path`, stopping at the first stage that fails
+pub fn execute(args: &Args, path: &str) -> Result<(), Failure> {
+    let src = fs::read_to_string(path).map_err(|e| {
+        eprintln!("error: cannot read `{path}`: {e}");
+        Failure::Io
+    })?;
+    let file = SourceFile::new(path, src);
+    let renderer = Renderer::new(&file, args.colour.enabled());
+
+    let mut lexer = Lexer::new(file.src());
+    let tokens = lexer.scan().map_err(|errors| {
+        let diags = errors.iter().map(LexError::diagnostic);
+        fail(&renderer, diags, Failure::Lex)
+    })?;
+    if args.command == Command::Tokens {
+        print!("{}", output::tokens(tokens, args.format));
+        return Ok(());
+    }
+
+    let ast = Parser::new(tokens).parse().map_err(|errors| {
+        let diags = errors.iter().map(|e| e.diagnostic());
+        fail(&renderer, diags, Failure::Parse)
+    })?;
+    match args.command {
+        Command::Ast => {
+            print!("{}", output::ast(&ast, args.format));
+            return Ok(());
+        }
+        Command::Fmt => return format(args, &file, tokens, &ast),
+        _ => {}
+    }
+
+    let types = analyse(&ast, &renderer)?;
+    match args.command {
+        Command::Run if args.interpret => interpret(&ast, &renderer),
+        Command::Run => run(&ast, &types, &renderer),
+        Command::Build => build(&ast, &types, &renderer),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrites the file in its canonical format, or with `--check` fails if that would change it
+fn format(args: &Args, file: &SourceFile, tokens: &[Token], ast: &[Node]) -> Result<(), Failure> {
+    let formatted = Formatter::new(file, tokens).format(ast);
+
+    // Refuse to write anything that doesn't parse back into the same program
+    let mut lexer = Lexer::new(&formatted);
+    let same = match lexer.scan() {
+        Ok(tokens) => Parser::new(tokens)
+            .parse()
+            .is_ok_and(|reparsed| same_program(ast, &reparsed)),
+        Err(_) => false,
+    };
+    if !same {
+        eprintln!(
+            "error: formatting `{}` would change its meaning",
+            file.name()
+        );
+        return Err(Failure::Format);
+    }
+
+    if formatted == file.src() {
+        return Ok(());
+    }
+    if args.check {
+        eprintln!("`{}` is not formatted", file.name());
+        return Err(Failure::Format);
+    }
+    fs::write(file.name(), formatted).map_err(|e| {
+        eprintln!("error: cannot write `{}`: {e}", file.name());
+        Failure::Io
+    })
+}
+
+/// Resolves names and checks the program, warnings are shown but only errors stop it. Returns
+/// the types the compiler needs.
+fn analyse(ast: &[Node], renderer: &Renderer) -> Result<Types, Failure> {
+    let (bindings, errors) = Resolver::new().resolve(ast);
+    for e in &errors {
+        eprint!("{}", renderer.render(&e.diagnostic()));
+    }
+    if errors.iter().any(|e| e.severity() == Severity::Error) {
+        return Err(Failure::Type);
+    }
+
+    Checker::new().check(ast, &bindings).map_err(|errors| {
+        let diags = errors.iter().map(|e| e.diagnostic());
+        fail(renderer, diags, Failure::Type)
+    })?;
+    TypeChecker::new()
+        .check(ast, &bindings)
+        .cloned()
+        .map_err(|errors| {
+            let diags = errors.iter().map(|e| e.diagnostic());
+            fail(renderer, diags, Failure::Type)
+        })
+}
+
+/// Compiles and runs every top level statement, printing the value of each expression
+fn run(ast: &[Node], types: &Types, renderer: &Renderer) -> Result<(), Failure> {
+    let mut vm = Vm::new();
+    for node in ast {
+        let script = compile(node, vm.globals(), types, renderer)?;
+        let value = vm
+            .run(Rc::new(script))
+            .map_err(|e| fail(renderer, [e.diagnostic()].into_iter(), Failure::Runtime))?;
+        print_value(value);
+    }
+    Ok(())
+}
+
+/// Like `run` but evaluates the AST directly
+fn interpret(ast: &[Node], renderer: &Renderer) -> Result<(), Failure> {
+    let mut interpreter = Interpreter::new();
+    for node in ast {
+        let value = interpreter
+            .eval(node)
+            .map_err(|e| fail(renderer, [e.diagnostic()].into_iter(), Failure::Runtime))?;
+        print_value(value);
+    }
+    Ok(())
+}
+
+/// Prints the bytecode every top level statement compiles to
+fn build(ast: &[Node], types: &Types, renderer: &Renderer) -> Result<(), Failure> {
+    let mut globals = Globals::new();
+    for (i, node) in ast.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let script = compile(node, &mut globals, types, renderer)?;
+        print!("{}", disassemble(&script, &globals));
+    }
+    Ok(())
+}
+
+fn compile(
+    node: &Node,
+    globals: &mut Globals,
+    types: &Types,
+    renderer: &Renderer,
+) -> Result<Function, Failure> {
+    Compiler::compile_script(node, globals, types)
+        .map_err(|e| fail(renderer, [e.diagnostic()].into_iter(), Failure::Compile))
+}
+
+fn print_value(value: Value) {
+    if !matches!(value, Value::Unit) {
+        println!("{value}");
+    }
+}
+
+/// Prints diagnostics and returns the failure they cause
+fn fail(renderer: &Renderer, diags: impl Iterator<Item = Diagnostic>, failure: Failure) -> Failure {
+    for diag in diags {
+        eprint!("{}", renderer.render(&diag));
+    }
+    failure
+}
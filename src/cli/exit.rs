@@ -0,0 +1,36 @@
+/// Why a command failed. Each reason exits with its own code so that scripts can tell which
+/// stage rejected a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The program started running and stopped with an error
+    Runtime,
+    /// The command line couldn't be understood
+    Usage,
+    /// The source contains something that isn't a token
+    Lex,
+    /// The tokens don't form a program
+    Parse,
+    /// Names, mutability, arity or types are wrong
+    Type,
+    /// The source file couldn't be read
+    Io,
+    /// The program is too large for the bytecode format
+    Compile,
+    /// `fmt --check` found a file that isn't formatted
+    Format,
+}
+
+impl Failure {
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Runtime => 1,
+            Self::Usage => 2,
+            Self::Lex => 3,
+            Self::Parse => 4,
+            Self::Type => 5,
+            Self::Io => 6,
+            Self::Compile => 7,
+            Self::Format => 8,
+        }
+    }
+}
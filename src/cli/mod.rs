@@ -0,0 +1,4 @@
+pub mod args;
+pub mod driver;
+pub mod exit;
+pub mod output;
@@ -0,0 +1,30 @@
+use crate::{
+    ast::node::Node,
+    lexer::token::Token,
+    serialize::{json::to_json, sexpr::to_sexpr, tree::ToTree},
+};
+
+use super::args::Format;
+
+/// Writes tokens one per line, each after its span
+pub fn tokens(tokens: &[Token], format: Format) -> String {
+    match format {
+        Format::Text => tokens
+            .iter()
+            .map(|t| format!("{:<10} {}\n", t.span.to_string(), t.kind))
+            .collect(),
+        Format::Debug => format!("{tokens:#?}\n"),
+        Format::Json => format!("{}\n", to_json(&tokens.to_tree())),
+        Format::Sexpr => format!("{}\n", to_sexpr(&tokens.to_tree())),
+    }
+}
+
+/// Writes each top level statement on its own line
+pub fn ast(nodes: &[Node], format: Format) -> String {
+    match format {
+        Format::Text => nodes.iter().map(|n| format!("{n}\n")).collect(),
+        Format::Debug => format!("{nodes:#?}\n"),
+        Format::Json => format!("{}\n", to_json(&nodes.to_tree())),
+        Format::Sexpr => format!("{}\n", to_sexpr(&nodes.to_tree())),
+    }
+}
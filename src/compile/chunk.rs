@@ -0,0 +1,130 @@
+use crate::{eval::value::Value, lexer::token::Span};
+
+/// Instructions understood by the VM. Each one is a single byte followed by its operands, which
+/// are `u8`s or little endian `u16`s. Jump offsets count from the end of the jump instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// `index: u16`, pushes a value from the constant pool
+    Constant,
+    Unit,
+    True,
+    False,
+    Pop,
+
+    /// `slot: u16`, slots count from the function being called, so parameters start at 1
+    GetLocal,
+    SetLocal,
+    /// `global: u16`, the slot the compiler's `Globals` gave the variable
+    GetGlobal,
+    SetGlobal,
+    DefineGlobal,
+    /// `global: u16`, pushes a pointer to a global
+    RefGlobal,
+
+    /// Wraps the value on top of the stack in a pointer. Locals that have their address taken
+    /// are stored like this so that pointers to them see assignments.
+    Box,
+    /// Writes the value on top of the stack through the pointer below it
+    Store,
+    /// Converts the value on top of the stack to the number type of the value below it, which is
+    /// removed. Used by `=` so numbers keep the type of the variable they are assigned to.
+    Coerce,
+
+    /// `op: u8`, the operator byte used by the AST
+    Unary,
+    Binary,
+    /// `op: u8`, checks the operand of `and` or `or` on top of the stack is a `bool`
+    Logical,
+    /// `type: u8`, the index of the type in `NumType::ALL`
+    Cast,
+    /// `type: u8`, converts a value stored in a variable declared with a number type
+    Convert,
+
+    /// `offset: u16`
+    Jump,
+    /// `offset: u16`, pops the condition
+    JumpIfFalse,
+    /// `offset: u16`, leave the condition on the stack for `and` and `or`
+    JumpIfFalseKeep,
+    JumpIfTrueKeep,
+    /// `offset: u16`, jumps backwards
+    Loop,
+
+    /// Checks the bound of a `for` range on top of the stack is an integer
+    RangeBound,
+    /// `slot: u16, offset: u16`, pushes the counter in `slot` if it is below the end of the range
+    /// in the slot after it, otherwise jumps
+    ForLoop,
+    /// `slot: u16`, increments the counter of a `for` loop
+    ForStep,
+
+    /// `args: u8, name: u16`, the name is only used to report errors
+    Call,
+    Return,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 29] = [
+        Self::Constant,
+        Self::Unit,
+        Self::True,
+        Self::False,
+        Self::Pop,
+        Self::GetLocal,
+        Self::SetLocal,
+        Self::GetGlobal,
+        Self::SetGlobal,
+        Self::DefineGlobal,
+        Self::RefGlobal,
+        Self::Box,
+        Self::Store,
+        Self::Coerce,
+        Self::Unary,
+        Self::Binary,
+        Self::Logical,
+        Self::Cast,
+        Self::Convert,
+        Self::Jump,
+        Self::JumpIfFalse,
+        Self::JumpIfFalseKeep,
+        Self::JumpIfTrueKeep,
+        Self::Loop,
+        Self::RangeBound,
+        Self::ForLoop,
+        Self::ForStep,
+        Self::Call,
+        Self::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// Bytecode for one function along with the values and spans it refers to
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// The span each byte of `code` was compiled from, used to report runtime errors
+    pub spans: Vec<Span>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: &Span) {
+        self.code.push(byte);
+        self.spans.push(span.clone());
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
+
+/// A compiled function, top level statements are compiled into functions without parameters
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+}
@@ -0,0 +1,700 @@
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+    ast::{
+        node::{
+            Assign, BinaryExpr, CallExpr, ForStmt, FunctionExpr, Ident, IfStmt, Let, Node,
+            UnaryExpr, WhileStmt,
+        },
+        number::NumType,
+    },
+    eval::value::Value,
+    lexer::token::Span,
+    typeck::{infer::Types, types::Type},
+};
+
+use super::{
+    chunk::{Chunk, Function, OpCode},
+    error::CompileError,
+    globals::Globals,
+};
+
+type CompileResult = Result<(), CompileError>;
+
+/// A variable that lives in a slot on the VM's stack
+struct Local {
+    name: String,
+    depth: usize,
+    /// The slot holds a pointer to the value rather than the value itself
+    boxed: bool,
+}
+
+/// Jumps out of a loop body, patched once the end of the loop is known
+struct Loop {
+    /// Locals declared before the body, `break` and `continue` pop every local after these
+    locals: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+/// Lowers the AST to bytecode one function at a time. Variables declared at the top level are
+/// globals stored in the slot `Globals` gives them, everything else is a local stored in a stack
+/// slot.
+pub struct Compiler<'a> {
+    chunk: Chunk,
+    globals: &'a mut Globals,
+    /// Types the type checker inferred, numbers are converted to the type of what they are
+    /// stored in
+    types: &'a Types,
+    locals: Vec<Local>,
+    depth: usize,
+    loops: Vec<Loop>,
+    /// Names in this function that have their address taken, locals with these names are boxed
+    boxed: HashSet<String>,
+    /// Span of the last instruction that had one, given to instructions that don't
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(
+        body: &[Node],
+        globals: &'a mut Globals,
+        types: &'a Types,
+        depth: usize,
+        span: Span,
+    ) -> Self {
+        let mut boxed = HashSet::new();
+        for node in body {
+            address_taken(node, &mut boxed);
+        }
+
+        Self {
+            chunk: Chunk::default(),
+            globals,
+            types,
+            // Slot 0 holds the function being called
+            locals: vec![Local {
+                name: String::new(),
+                depth: 0usize,
+                boxed: false,
+            }],
+            depth,
+            loops: Vec::new(),
+            boxed,
+            span,
+        }
+    }
+
+    /// Compiles a top level statement into a function that returns the statement's value. The
+    /// globals have to be the ones the statements before it were compiled with, and the types
+    /// the ones the type checker found for it.
+    pub fn compile_script(
+        node: &Node,
+        globals: &mut Globals,
+        types: &Types,
+    ) -> Result<Function, CompileError> {
+        let body = std::slice::from_ref(node);
+        let mut compiler = Compiler::new(body, globals, types, 0usize, Span::from(0, 1));
+        if is_expression(node) {
+            compiler.expr(node)?;
+        } else {
+            compiler.statement(node)?;
+            compiler.emit(OpCode::Unit);
+        }
+        compiler.emit(OpCode::Return);
+
+        Ok(Function {
+            name: "<script>".to_string(),
+            arity: 0usize,
+            chunk: compiler.chunk,
+        })
+    }
+
+    fn compile_function(
+        func: &FunctionExpr,
+        globals: &mut Globals,
+        types: &Types,
+    ) -> Result<Function, CompileError> {
+        let sig = &func.signature;
+        let mut compiler = Compiler::new(&func.body, globals, types, 1usize, sig.name.span.clone());
+
+        // The function is in slot 0, which is how it reaches itself when it isn't a global
+        compiler.locals[0].name = sig.name.name.clone();
+        for param in &sig.params {
+            compiler.add_local(&param.name)?;
+        }
+
+        // Arguments are converted to the parameter's type and boxed on entry
+        for (i, param) in sig.params.iter().enumerate() {
+            let slot = i as u16 + 1;
+            let ty = compiler.num_type(&param.name.span);
+            let boxed = compiler.locals[slot as usize].boxed;
+            if ty.is_none() && !boxed {
+                continue;
+            }
+
+            compiler.emit_u16(OpCode::GetLocal, slot, &param.name.span);
+            if let Some(ty) = ty {
+                compiler.emit_type(OpCode::Convert, ty, &param.annotation.span);
+            }
+            if boxed {
+                compiler.emit(OpCode::Box);
+            }
+            compiler.emit_u16(OpCode::SetLocal, slot, &param.name.span);
+        }
+
+        for node in &func.body {
+            compiler.statement(node)?;
+        }
+        compiler.emit(OpCode::Unit);
+        compiler.emit(OpCode::Return);
+
+        Ok(Function {
+            name: sig.name.name.clone(),
+            arity: sig.params.len(),
+            chunk: compiler.chunk,
+        })
+    }
+
+    /// Compiles a node that leaves the stack the way it found it
+    fn statement(&mut self, node: &Node) -> CompileResult {
+        match node {
+            Node::Let(l) => self.let_stmt(l),
+            Node::Assign(a) => self.assign(a),
+            Node::Function(func) => {
+                let function = Compiler::compile_function(func, self.globals, self.types)?;
+                let name = &func.signature.name;
+                self.constant(Value::Compiled(Rc::new(function)), &name.span)?;
+                self.declare(name)
+            }
+            Node::Return(r) => {
+                match &r.value {
+                    Some(value) => self.expr(value)?,
+                    None => self.emit(OpCode::Unit),
+                }
+                self.emit_at(OpCode::Return, &r.span);
+                Ok(())
+            }
+            Node::Block(body) => self.block(body),
+            Node::If(stmt) => self.if_stmt(stmt),
+            Node::While(stmt) => self.while_stmt(stmt),
+            Node::For(stmt) => self.for_stmt(stmt),
+            Node::Break(span) => self.exit_loop(true, span),
+            Node::Continue(span) => self.exit_loop(false, span),
+            _ => {
+                self.expr(node)?;
+                self.emit(OpCode::Pop);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles a node that pushes its value
+    fn expr(&mut self, node: &Node) -> CompileResult {
+        match node {
+            Node::Integer(v, ty) => self.constant(Value::Integer(*v, *ty), &self.span.clone()),
+            Node::Number(v, ty) => self.constant(Value::Number(*v, *ty), &self.span.clone()),
+            Node::Str(v) => self.constant(Value::Str(v.clone()), &self.span.clone()),
+            Node::Bool(true) => {
+                self.emit(OpCode::True);
+                Ok(())
+            }
+            Node::Bool(false) => {
+                self.emit(OpCode::False);
+                Ok(())
+            }
+            Node::Ident(ident) => self.variable(ident),
+            Node::Group(inner) => self.expr(inner),
+            Node::UnaryExpr(e) => self.unary(e),
+            Node::BinaryExpr(e) => self.binary(e),
+            Node::Cast(c) => {
+                self.expr(&c.value)?;
+                // The checker only lets number types through
+                let ty = NumType::from_name(&c.ty.name).unwrap_or(NumType::I64);
+                self.emit_type(OpCode::Cast, ty, &c.span);
+                Ok(())
+            }
+            Node::Call(c) => self.call(c),
+            // Statements evaluate to `()` like they do in the interpreter
+            _ => {
+                self.statement(node)?;
+                self.emit(OpCode::Unit);
+                Ok(())
+            }
+        }
+    }
+
+    fn block(&mut self, body: &[Node]) -> CompileResult {
+        self.depth += 1;
+        for node in body {
+            self.statement(node)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Leaves the innermost scope and pops its locals
+    fn end_scope(&mut self) {
+        self.depth -= 1;
+        while self.locals.last().is_some_and(|l| l.depth > self.depth) {
+            self.locals.pop();
+            self.emit(OpCode::Pop);
+        }
+    }
+
+    fn let_stmt(&mut self, l: &Let) -> CompileResult {
+        self.expr(&l.value)?;
+        if let Some(annotation) = &l.annotation {
+            if let Some(ty) = self.num_type(&l.name.span) {
+                self.emit_type(OpCode::Convert, ty, &annotation.span);
+            }
+        }
+        self.declare(&l.name)
+    }
+
+    /// Stores the value on top of the stack in a new variable
+    fn declare(&mut self, name: &Ident) -> CompileResult {
+        if self.depth == 0 {
+            let index = self.globals.index(&name.name, &name.span)?;
+            self.emit_u16(OpCode::DefineGlobal, index, &name.span);
+            return Ok(());
+        }
+
+        if self.boxed.contains(&name.name) {
+            self.emit_at(OpCode::Box, &name.span);
+        }
+        self.add_local(name)
+    }
+
+    fn add_local(&mut self, name: &Ident) -> CompileResult {
+        let boxed = self.boxed.contains(&name.name);
+        self.push_local(name.name.clone(), boxed, &name.span)
+    }
+
+    fn push_local(&mut self, name: String, boxed: bool, span: &Span) -> CompileResult {
+        if self.locals.len() > u16::MAX as usize {
+            return Err(CompileError::TooManyLocals { span: span.clone() });
+        }
+        self.locals.push(Local {
+            name,
+            depth: self.depth,
+            boxed,
+        });
+        Ok(())
+    }
+
+    /// The number type the type checker gave the declaration at `span`, if it is one
+    fn num_type(&self, span: &Span) -> Option<NumType> {
+        match self.types.get(span) {
+            Some(Type::Num(ty)) => Some(*ty),
+            _ => None,
+        }
+    }
+
+    /// Finds the slot of a local and whether it is boxed, `None` means the name is a global
+    fn resolve_local(&self, name: &str) -> Option<(u16, bool)> {
+        self.locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, l)| l.name == name)
+            .map(|(slot, l)| (slot as u16, l.boxed))
+    }
+
+    fn variable(&mut self, ident: &Ident) -> CompileResult {
+        match self.resolve_local(&ident.name) {
+            Some((slot, boxed)) => {
+                self.emit_u16(OpCode::GetLocal, slot, &ident.span);
+                if boxed {
+                    self.emit_byte(OpCode::Unary, b'*', &ident.span);
+                }
+            }
+            None => {
+                let index = self.globals.index(&ident.name, &ident.span)?;
+                self.emit_u16(OpCode::GetGlobal, index, &ident.span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mutability has already been checked, so this only has to find the variable
+    fn assign(&mut self, a: &Assign) -> CompileResult {
+        let target = &a.target;
+        match self.resolve_local(&target.name) {
+            Some((slot, true)) => {
+                self.emit_u16(OpCode::GetLocal, slot, &target.span);
+                self.variable(target)?;
+                self.expr(&a.value)?;
+                self.update(a);
+                self.emit_at(OpCode::Store, &a.span);
+            }
+            Some((slot, false)) => {
+                self.variable(target)?;
+                self.expr(&a.value)?;
+                self.update(a);
+                self.emit_u16(OpCode::SetLocal, slot, &a.span);
+            }
+            None => {
+                let index = self.globals.index(&target.name, &target.span)?;
+                self.emit_u16(OpCode::GetGlobal, index, &target.span);
+                self.expr(&a.value)?;
+                self.update(a);
+                self.emit_u16(OpCode::SetGlobal, index, &target.span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Combines the current value of an assignment's target with the new one
+    fn update(&mut self, a: &Assign) {
+        if a.op == b'=' {
+            self.emit_at(OpCode::Coerce, &a.span);
+        } else {
+            self.emit_byte(OpCode::Binary, a.op, &a.span);
+        }
+    }
+
+    fn unary(&mut self, e: &UnaryExpr) -> CompileResult {
+        // Taking the address of a variable shares its storage, anything else gets new storage
+        if e.op == b'&' {
+            if let Node::Ident(ident) = e.rhs.as_ref() {
+                match self.resolve_local(&ident.name) {
+                    Some((slot, true)) => self.emit_u16(OpCode::GetLocal, slot, &ident.span),
+                    Some((slot, false)) => {
+                        self.emit_u16(OpCode::GetLocal, slot, &ident.span);
+                        self.emit_at(OpCode::Box, &e.span);
+                    }
+                    None => {
+                        let index = self.globals.index(&ident.name, &ident.span)?;
+                        self.emit_u16(OpCode::RefGlobal, index, &ident.span);
+                    }
+                }
+                return Ok(());
+            }
+            self.expr(&e.rhs)?;
+            self.emit_at(OpCode::Box, &e.span);
+            return Ok(());
+        }
+
+        self.expr(&e.rhs)?;
+        self.emit_byte(OpCode::Unary, e.op, &e.span);
+        Ok(())
+    }
+
+    fn binary(&mut self, e: &BinaryExpr) -> CompileResult {
+        self.expr(&e.lhs)?;
+
+        // `and` and `or` only evaluate the RHS when the LHS doesn't decide the result
+        if matches!(e.op, b'&' | b'|') {
+            self.emit_byte(OpCode::Logical, e.op, &e.span);
+            let op = if e.op == b'&' {
+                OpCode::JumpIfFalseKeep
+            } else {
+                OpCode::JumpIfTrueKeep
+            };
+            let end = self.emit_jump(op, &e.span);
+            self.emit(OpCode::Pop);
+            self.expr(&e.rhs)?;
+            self.emit_byte(OpCode::Logical, e.op, &e.span);
+            return self.patch(end);
+        }
+
+        self.expr(&e.rhs)?;
+        self.emit_byte(OpCode::Binary, e.op, &e.span);
+        Ok(())
+    }
+
+    fn call(&mut self, c: &CallExpr) -> CompileResult {
+        let Ok(argc) = u8::try_from(c.args.len()) else {
+            return Err(CompileError::TooManyArguments {
+                span: c.span.clone(),
+            });
+        };
+
+        self.variable(&c.callee)?;
+        for arg in &c.args {
+            self.expr(arg)?;
+        }
+        let name = self.name(&c.callee.name, &c.callee.span)?;
+        self.emit_byte(OpCode::Call, argc, &c.span);
+        self.write_u16(name);
+        Ok(())
+    }
+
+    fn if_stmt(&mut self, stmt: &IfStmt) -> CompileResult {
+        let mut ends = Vec::new();
+        for branch in &stmt.branches {
+            self.expr(&branch.cond)?;
+            let next = self.emit_jump(OpCode::JumpIfFalse, &branch.span);
+            self.block(&branch.body)?;
+            ends.push(self.emit_jump(OpCode::Jump, &branch.span));
+            self.patch(next)?;
+        }
+        if let Some(body) = &stmt.otherwise {
+            self.block(body)?;
+        }
+        for end in ends {
+            self.patch(end)?;
+        }
+        Ok(())
+    }
+
+    fn while_stmt(&mut self, stmt: &WhileStmt) -> CompileResult {
+        let start = self.chunk.code.len();
+        self.expr(&stmt.cond)?;
+        let exit = self.emit_jump(OpCode::JumpIfFalse, &stmt.span);
+
+        let body = self.loop_body(&stmt.body)?;
+        for jump in body.continues {
+            self.patch(jump)?;
+        }
+        self.emit_loop(start, &stmt.span)?;
+
+        self.patch(exit)?;
+        for jump in body.breaks {
+            self.patch(jump)?;
+        }
+        Ok(())
+    }
+
+    /// The counter and the end of the range are kept in hidden locals, and every iteration
+    /// pushes a copy of the counter as the loop variable
+    fn for_stmt(&mut self, stmt: &ForStmt) -> CompileResult {
+        // The checker gives the loop variable the type of the start, the end is converted to it
+        let ty = self.num_type(&stmt.var.span).unwrap_or(NumType::I64);
+        self.depth += 1;
+        self.expr(&stmt.start)?;
+        self.emit_at(OpCode::RangeBound, &stmt.span);
+        self.push_local(String::new(), false, &stmt.span)?;
+        let counter = (self.locals.len() - 1) as u16;
+        self.expr(&stmt.end)?;
+        self.emit_at(OpCode::RangeBound, &stmt.span);
+        self.emit_type(OpCode::Convert, ty, &stmt.end_span);
+        self.push_local(String::new(), false, &stmt.span)?;
+
+        let start = self.chunk.code.len();
+        self.emit_u16(OpCode::ForLoop, counter, &stmt.span);
+        let exit = self.placeholder();
+
+        self.depth += 1;
+        if self.boxed.contains(&stmt.var.name) {
+            self.emit_at(OpCode::Box, &stmt.var.span);
+        }
+        self.add_local(&stmt.var)?;
+        let body = self.loop_body(&stmt.body)?;
+        for jump in body.continues {
+            self.patch(jump)?;
+        }
+        self.end_scope();
+        self.emit_u16(OpCode::ForStep, counter, &stmt.span);
+        self.emit_loop(start, &stmt.span)?;
+
+        // `break` leaves the loop variable behind, finishing the range doesn't push one
+        if !body.breaks.is_empty() {
+            for jump in body.breaks {
+                self.patch(jump)?;
+            }
+            self.emit(OpCode::Pop);
+        }
+        self.patch(exit)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Compiles a loop body and returns the jumps out of it that still need patching
+    fn loop_body(&mut self, body: &[Node]) -> Result<Loop, CompileError> {
+        self.loops.push(Loop {
+            locals: self.locals.len(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.block(body)?;
+        Ok(self.loops.pop().expect("loop body was pushed above"))
+    }
+
+    /// Pops the locals declared inside the loop body and jumps out of it
+    fn exit_loop(&mut self, is_break: bool, span: &Span) -> CompileResult {
+        // The checker only allows these inside of loops
+        let Some(locals) = self.loops.last().map(|l| l.locals) else {
+            return Ok(());
+        };
+        for _ in locals..self.locals.len() {
+            self.emit_at(OpCode::Pop, span);
+        }
+
+        let jump = self.emit_jump(OpCode::Jump, span);
+        if let Some(lp) = self.loops.last_mut() {
+            if is_break {
+                lp.breaks.push(jump);
+            } else {
+                lp.continues.push(jump);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a callee's name to the constant pool for error messages, reusing it if it is already
+    /// there
+    fn name(&mut self, name: &str, span: &Span) -> Result<u16, CompileError> {
+        let existing = self
+            .chunk
+            .constants
+            .iter()
+            .position(|c| matches!(c, Value::Str(s) if s == name));
+        match existing {
+            Some(index) => Ok(index as u16),
+            None => self.add_constant(Value::Str(name.to_string()), span),
+        }
+    }
+
+    fn add_constant(&mut self, value: Value, span: &Span) -> Result<u16, CompileError> {
+        let index = self.chunk.constants.len();
+        let index = u16::try_from(index)
+            .map_err(|_| CompileError::TooManyConstants { span: span.clone() })?;
+        self.chunk.constants.push(value);
+        Ok(index)
+    }
+
+    fn constant(&mut self, value: Value, span: &Span) -> CompileResult {
+        let index = self.add_constant(value, span)?;
+        self.emit_u16(OpCode::Constant, index, span);
+        Ok(())
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.chunk.write(op as u8, &self.span);
+    }
+
+    fn emit_at(&mut self, op: OpCode, span: &Span) {
+        self.span = span.clone();
+        self.emit(op);
+    }
+
+    fn emit_byte(&mut self, op: OpCode, byte: u8, span: &Span) {
+        self.emit_at(op, span);
+        self.chunk.write(byte, span);
+    }
+
+    fn emit_u16(&mut self, op: OpCode, value: u16, span: &Span) {
+        self.emit_at(op, span);
+        self.write_u16(value);
+    }
+
+    fn emit_type(&mut self, op: OpCode, ty: NumType, span: &Span) {
+        let index = NumType::ALL.iter().position(|t| *t == ty).unwrap_or(0usize);
+        self.emit_byte(op, index as u8, span);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        for byte in value.to_le_bytes() {
+            self.chunk.write(byte, &self.span);
+        }
+    }
+
+    /// Writes a jump offset to fill in later and returns where it is
+    fn placeholder(&mut self) -> usize {
+        self.write_u16(u16::MAX);
+        self.chunk.code.len() - 2
+    }
+
+    fn emit_jump(&mut self, op: OpCode, span: &Span) -> usize {
+        self.emit_at(op, span);
+        self.placeholder()
+    }
+
+    /// Points the jump offset at `at` to the end of the code so far
+    fn patch(&mut self, at: usize) -> CompileResult {
+        let offset = self.chunk.code.len() - at - 2;
+        let offset = u16::try_from(offset).map_err(|_| CompileError::JumpTooFar {
+            span: self.chunk.spans[at].clone(),
+        })?;
+        self.chunk.code[at..at + 2].copy_from_slice(&offset.to_le_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize, span: &Span) -> CompileResult {
+        self.emit_at(OpCode::Loop, span);
+        let offset = self.chunk.code.len() + 2 - start;
+        let offset =
+            u16::try_from(offset).map_err(|_| CompileError::JumpTooFar { span: span.clone() })?;
+        self.write_u16(offset);
+        Ok(())
+    }
+}
+
+/// Whether a top level node has a value worth returning from its script
+fn is_expression(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Integer(..)
+            | Node::Number(..)
+            | Node::Bool(_)
+            | Node::Str(_)
+            | Node::Ident(_)
+            | Node::Group(_)
+            | Node::UnaryExpr(_)
+            | Node::BinaryExpr(_)
+            | Node::Cast(_)
+            | Node::Call(_)
+    )
+}
+
+/// Collects the names used with `&`, not looking inside of other functions since they have their
+/// own locals
+fn address_taken(node: &Node, names: &mut HashSet<String>) {
+    let visit = |nodes: &[Node], names: &mut HashSet<String>| {
+        for node in nodes {
+            address_taken(node, names);
+        }
+    };
+
+    match node {
+        Node::UnaryExpr(e) => {
+            if let (b'&', Node::Ident(ident)) = (e.op, e.rhs.as_ref()) {
+                names.insert(ident.name.clone());
+            }
+            address_taken(&e.rhs, names);
+        }
+        Node::Group(inner) => address_taken(inner, names),
+        Node::BinaryExpr(e) => {
+            address_taken(&e.lhs, names);
+            address_taken(&e.rhs, names);
+        }
+        Node::Cast(c) => address_taken(&c.value, names),
+        Node::Let(l) => address_taken(&l.value, names),
+        Node::Assign(a) => address_taken(&a.value, names),
+        Node::Call(c) => visit(&c.args, names),
+        Node::Return(r) => {
+            if let Some(value) = &r.value {
+                address_taken(value, names);
+            }
+        }
+        Node::Block(body) => visit(body, names),
+        Node::If(stmt) => {
+            for branch in &stmt.branches {
+                address_taken(&branch.cond, names);
+                visit(&branch.body, names);
+            }
+            if let Some(body) = &stmt.otherwise {
+                visit(body, names);
+            }
+        }
+        Node::While(stmt) => {
+            address_taken(&stmt.cond, names);
+            visit(&stmt.body, names);
+        }
+        Node::For(stmt) => {
+            address_taken(&stmt.start, names);
+            address_taken(&stmt.end, names);
+            visit(&stmt.body, names);
+        }
+        Node::Function(_)
+        | Node::Integer(..)
+        | Node::Number(..)
+        | Node::Bool(_)
+        | Node::Str(_)
+        | Node::Ident(_)
+        | Node::Break(_)
+        | Node::Continue(_) => {}
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::{
+        node::{op_str, unary_op_str},
+        number::NumType,
+    },
+    eval::value::Value,
+};
+
+use super::{
+    chunk::{Chunk, Function, OpCode},
+    globals::Globals,
+};
+
+/// Writes a function's bytecode one instruction per line, followed by every function it defines.
+/// `globals` has to be what the function was compiled with, to name the globals it uses.
+pub fn disassemble(function: &Function, globals: &Globals) -> String {
+    let mut out = String::new();
+    write_function(function, globals, &mut out);
+    out
+}
+
+fn write_function(function: &Function, globals: &Globals, out: &mut String) {
+    let _ = writeln!(out, "== {} ==", function.name);
+    let chunk = &function.chunk;
+    let mut offset = 0usize;
+    while offset < chunk.code.len() {
+        offset = write_instruction(chunk, globals, offset, out);
+    }
+
+    for constant in &chunk.constants {
+        if let Value::Compiled(inner) = constant {
+            out.push('\n');
+            write_function(inner, globals, out);
+        }
+    }
+}
+
+/// Writes the instruction at `offset` and returns the offset of the next one
+fn write_instruction(chunk: &Chunk, globals: &Globals, offset: usize, out: &mut String) -> usize {
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_byte(byte) else {
+        let _ = writeln!(out, "{offset:04}  <invalid opcode {byte}>");
+        return offset + 1;
+    };
+
+    let byte_at = |i: usize| chunk.code[offset + i];
+    let u16_at = |i: usize| chunk.read_u16(offset + i) as usize;
+    let (operands, len) = match op {
+        OpCode::Constant => {
+            let index = u16_at(1);
+            (
+                format!("{index:<5} {}", describe(&chunk.constants[index])),
+                3,
+            )
+        }
+        OpCode::GetGlobal | OpCode::SetGlobal | OpCode::DefineGlobal | OpCode::RefGlobal => {
+            let index = chunk.read_u16(offset + 1);
+            (format!("{index:<5} {:?}", globals.name(index)), 3)
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::ForStep => (u16_at(1).to_string(), 3),
+        OpCode::Unary => (unary_op_str(byte_at(1)).to_string(), 2),
+        OpCode::Binary | OpCode::Logical => (op_str(byte_at(1)).to_string(), 2),
+        OpCode::Cast | OpCode::Convert => (NumType::ALL[byte_at(1) as usize].to_string(), 2),
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfFalseKeep | OpCode::JumpIfTrueKeep => {
+            (format!("-> {:04}", offset + 3 + u16_at(1)), 3)
+        }
+        OpCode::Loop => (format!("-> {:04}", offset + 3 - u16_at(1)), 3),
+        OpCode::ForLoop => (
+            format!("{:<5} -> {:04}", u16_at(1), offset + 5 + u16_at(3)),
+            5,
+        ),
+        OpCode::Call => {
+            let name = describe(&chunk.constants[u16_at(2)]);
+            (format!("{:<5} {name}", byte_at(1)), 4)
+        }
+        _ => (String::new(), 1),
+    };
+
+    let span = chunk.spans[offset].to_string();
+    let line = format!(
+        "{offset:04}  {span:<10} {:<16} {operands}",
+        format!("{op:?}")
+    );
+    let _ = writeln!(out, "{}", line.trim_end());
+    offset + len
+}
+
+/// Shows strings with quotes so they can be told apart from other constants
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Str(s) => format!("{s:?}"),
+        v => v.to_string(),
+    }
+}
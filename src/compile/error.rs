@@ -0,0 +1,62 @@
+use std::fmt;
+
+use crate::{diagnostics::diagnostic::Diagnostic, lexer::token::Span};
+
+/// Limits of the bytecode format that a program ran into while being compiled
+#[derive(Debug, Clone)]
+pub enum CompileError {
+    /// A function uses more constants than a `u16` can index
+    TooManyConstants { span: Span },
+
+    /// A function declares more locals than a `u16` can index
+    TooManyLocals { span: Span },
+
+    /// A jump has to cover more bytecode than a `u16` offset can
+    JumpTooFar { span: Span },
+
+    /// A call passes more arguments than a `u8` can count
+    TooManyArguments { span: Span },
+
+    /// The program declares more globals than a `u16` can index
+    TooManyGlobals { span: Span },
+}
+
+impl CompileError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::TooManyConstants { span } => span,
+            Self::TooManyLocals { span } => span,
+            Self::JumpTooFar { span } => span,
+            Self::TooManyArguments { span } => span,
+            Self::TooManyGlobals { span } => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::TooManyConstants { .. } | Self::TooManyLocals { .. } => {
+                diag.with_note("split the function into smaller ones")
+            }
+            Self::JumpTooFar { .. } => diag.with_label("this body is too large"),
+            Self::TooManyArguments { .. } => {
+                diag.with_label(format!("at most {} arguments are allowed", u8::MAX))
+            }
+            Self::TooManyGlobals { .. } => {
+                diag.with_note("move some of the top level code into functions")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyConstants { .. } => write!(f, "too many constants in one function"),
+            Self::TooManyLocals { .. } => write!(f, "too many local variables in one function"),
+            Self::JumpTooFar { .. } => write!(f, "too much code to jump over"),
+            Self::TooManyArguments { .. } => write!(f, "too many arguments in one call"),
+            Self::TooManyGlobals { .. } => write!(f, "too many global variables"),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use crate::lexer::token::Span;
+
+use super::error::CompileError;
+
+/// Gives every global a slot index when it is compiled, so the VM can find it without hashing its
+/// name. It is kept between compilations so that top level statements compiled one at a time
+/// agree on where each global lives.
+#[derive(Debug, Clone, Default)]
+pub struct Globals {
+    names: Vec<String>,
+    indices: HashMap<String, u16>,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index of the global called `name`, giving it the next free one the first time it is seen
+    pub fn index(&mut self, name: &str, span: &Span) -> Result<u16, CompileError> {
+        if let Some(index) = self.indices.get(name) {
+            return Ok(*index);
+        }
+        let index = u16::try_from(self.names.len())
+            .map_err(|_| CompileError::TooManyGlobals { span: span.clone() })?;
+        self.names.push(name.to_string());
+        self.indices.insert(name.to_string(), index);
+        Ok(index)
+    }
+
+    /// Name of the global at `index`, used to report errors and show bytecode
+    pub fn name(&self, index: u16) -> &str {
+        self.names.get(index as usize).map_or("?", String::as_str)
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Write;
+
+use crate::lexer::source::SourceFile;
+
+use super::diagnostic::{Diagnostic, Severity};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Width a tab is expanded to when printing source lines
+const TAB_WIDTH: usize = 4;
+
+/// A single underline drawn below a source line
+struct Mark<'a> {
+    line: usize,
+    /// First and last character columns covered, both inclusive
+    start: usize,
+    end: usize,
+    primary: bool,
+    message: Option<&'a str>,
+}
+
+/// Renders diagnostics the way rustc does, with the offending source lines, a line number
+/// gutter and `^^^` under the span:
+///
+/// ```text
+/// error: expected an expression, found `}`
+///  --> main.sk:1:5
+///   |
+/// 1 | 4 * } 5
+///   |     ^
+/// ```
+pub struct Renderer<'a> {
+    file: &'a SourceFile,
+    colour: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file: &'a SourceFile, colour: bool) -> Self {
+        Self { file, colour }
+    }
+
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let mut out = String::new();
+        let marks = self.marks(diag);
+
+        // Line numbers are right aligned in the gutter
+        let width = marks
+            .iter()
+            .map(|m| m.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(width);
+        let severity = self.severity_style(diag.severity);
+
+        // Header and location
+        let (start, _) = self.file.span_position(&diag.span);
+        let _ = writeln!(
+            out,
+            "{}{}{}: {}",
+            self.style(severity),
+            diag.severity,
+            self.style(RESET),
+            self.bold(&diag.message)
+        );
+        let _ = writeln!(
+            out,
+            "{pad}{}-->{} {}:{start}",
+            self.style(BLUE),
+            self.style(RESET),
+            self.file.name()
+        );
+        let _ = writeln!(out, "{}", self.gutter(&pad));
+
+        // Every line touched by a mark, in order
+        let mut lines: Vec<usize> = marks.iter().map(|m| m.line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut previous: Option<usize> = None;
+        for line in lines {
+            if previous.is_some_and(|p| p + 1 < line) {
+                let _ = writeln!(out, "{}...{}", self.style(BLUE), self.style(RESET));
+            }
+            previous = Some(line);
+
+            let text = self.file.line_text(line);
+            let _ = writeln!(
+                out,
+                "{}{line:>width$} |{} {}",
+                self.style(BLUE),
+                self.style(RESET),
+                expand_tabs(text)
+            );
+
+            // Primary marks come first so the main error is closest to the source line
+            let mut on_line: Vec<&Mark> = marks.iter().filter(|m| m.line == line).collect();
+            on_line.sort_by_key(|m| !m.primary);
+
+            for mark in on_line {
+                let offset = display_width(text, 1, mark.start);
+                let len = display_width(text, mark.start, mark.end + 1).max(1);
+                let (symbol, colour) = if mark.primary {
+                    ('^', severity)
+                } else {
+                    ('-', BLUE)
+                };
+
+                let mut underline = " ".repeat(offset);
+                underline.push_str(self.style(colour));
+                underline.extend(std::iter::repeat_n(symbol, len));
+                if let Some(message) = mark.message {
+                    underline.push(' ');
+                    underline.push_str(message);
+                }
+                underline.push_str(self.style(RESET));
+
+                let _ = writeln!(out, "{} {underline}", self.gutter(&pad));
+            }
+        }
+
+        for note in &diag.notes {
+            let _ = writeln!(
+                out,
+                "{pad} {}={} {}: {note}",
+                self.style(BLUE),
+                self.style(RESET),
+                self.bold("note")
+            );
+        }
+
+        out
+    }
+
+    /// Splits every span in the diagnostic into one mark per line it covers, the label message
+    /// is only written under the last line
+    fn marks<'d>(&self, diag: &'d Diagnostic) -> Vec<Mark<'d>> {
+        let primary = std::iter::once((&diag.span, diag.label.as_deref(), true));
+        let secondary = diag
+            .secondary
+            .iter()
+            .map(|l| (&l.span, Some(l.message.as_str()), false));
+
+        let mut marks = Vec::new();
+        for (span, message, is_primary) in primary.chain(secondary) {
+            let (start, end) = self.file.span_position(span);
+            for line in start.line..=end.line {
+                let line_len = self.file.line_text(line).chars().count();
+                marks.push(Mark {
+                    line,
+                    start: if line == start.line { start.column } else { 1 },
+                    end: if line == end.line {
+                        end.column
+                    } else {
+                        line_len.max(1)
+                    },
+                    primary: is_primary,
+                    message: if line == end.line { message } else { None },
+                });
+            }
+        }
+        marks
+    }
+
+    fn severity_style(&self, severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+
+    fn gutter(&self, pad: &str) -> String {
+        format!("{pad} {}|{}", self.style(BLUE), self.style(RESET))
+    }
+
+    fn bold(&self, text: &str) -> String {
+        format!("{}{text}{}", self.style(BOLD), self.style(RESET))
+    }
+
+    /// Returns the escape code if colour is enabled, otherwise nothing
+    fn style(&self, code: &'static str) -> &'static str {
+        if self.colour {
+            code
+        } else {
+            ""
+        }
+    }
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// Number of terminal columns taken by the characters in columns `from..to` of `text`, columns
+/// past the end of the line count as one each so spans at the end of a line stay visible
+fn display_width(text: &str, from: usize, to: usize) -> usize {
+    let widths: Vec<usize> = text
+        .chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .collect();
+
+    (from..to)
+        .map(|col| widths.get(col - 1).copied().unwrap_or(1))
+        .sum()
+}
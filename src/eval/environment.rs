@@ -0,0 +1,77 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::value::Value;
+
+/// Storage for a single variable, shared with any pointers taken to it
+pub type Slot = Rc<RefCell<Value>>;
+
+type Scope = HashMap<String, Slot>;
+
+/// Holds the values of every variable that is currently in scope
+pub struct Environment {
+    scopes: Vec<Scope>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    /// Declares a variable in the innermost scope, replacing any previous one with the same name
+    pub fn define(&mut self, name: &str, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Rc::new(RefCell::new(value)));
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.slot(name).map(|slot| slot.borrow().clone())
+    }
+
+    /// Returns the storage of the closest variable called `name`, used to take its address
+    pub fn slot(&self, name: &str) -> Option<Slot> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Sets up a fresh scope for a call to the function `name`. Functions only see the global
+    /// scope and their own name, so everything else is moved out of the way and has to be given
+    /// back to `exit_function`.
+    pub fn enter_function(&mut self, name: &str, callee: Value) -> Vec<Scope> {
+        let saved = self.scopes.split_off(1);
+        self.push_scope();
+        self.define(name, callee);
+        self.push_scope();
+        saved
+    }
+
+    pub fn exit_function(&mut self, saved: Vec<Scope>) {
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+    }
+
+    /// Updates the closest variable called `name`, returns `false` if there isn't one. The value
+    /// is written into the existing storage so pointers to the variable see the change.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self.slot(name) {
+            Some(slot) => {
+                *slot.borrow_mut() = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
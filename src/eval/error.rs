@@ -0,0 +1,128 @@
+use std::fmt;
+
+use crate::{diagnostics::diagnostic::Diagnostic, lexer::token::Span};
+
+/// Errors raised while evaluating a program
+#[derive(Debug, Clone)]
+pub enum RuntimeError {
+    /// Integer division or remainder with a zero divisor
+    DivisionByZero { span: Span },
+
+    /// An integer raised to a negative power, which has no integer result
+    NegativeExponent { span: Span },
+
+    /// An integer operation or cast produced a result that doesn't fit in the type `ty`
+    Overflow { ty: &'static str, span: Span },
+
+    /// An identifier that doesn't refer to any value
+    UndefinedVariable { name: String, span: Span },
+
+    /// A value that isn't a function was called
+    NotCallable { name: String, span: Span },
+
+    /// A function was called with the wrong number of arguments
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+
+    /// Function calls nested deeper than the interpreter allows
+    StackOverflow { span: Span },
+
+    /// A condition evaluated to something that can't be used as one
+    InvalidCondition { ty: &'static str, span: Span },
+
+    /// A binary operator was applied to numbers of two different types
+    MismatchedTypes {
+        op: &'static str,
+        lhs: &'static str,
+        rhs: &'static str,
+        span: Span,
+    },
+
+    /// `as` was applied to something that isn't a number
+    InvalidCast {
+        from: &'static str,
+        to: &'static str,
+        span: Span,
+    },
+
+    /// An operator was applied to a value of a type it doesn't support
+    InvalidOperand {
+        op: &'static str,
+        ty: &'static str,
+        span: Span,
+    },
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::DivisionByZero { span } => span,
+            Self::NegativeExponent { span } => span,
+            Self::Overflow { span, .. } => span,
+            Self::UndefinedVariable { span, .. } => span,
+            Self::NotCallable { span, .. } => span,
+            Self::ArityMismatch { span, .. } => span,
+            Self::StackOverflow { span } => span,
+            Self::InvalidCondition { span, .. } => span,
+            Self::MismatchedTypes { span, .. } => span,
+            Self::InvalidCast { span, .. } => span,
+            Self::InvalidOperand { span, .. } => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::DivisionByZero { .. } => diag.with_label("divisor is zero"),
+            Self::NegativeExponent { .. } => diag
+                .with_label("exponent is negative")
+                .with_note("convert the base with `as f64` for a fractional result"),
+            Self::Overflow { ty, .. } => diag.with_label(format!("result does not fit in `{ty}`")),
+            Self::UndefinedVariable { .. } => diag.with_label("not found"),
+            Self::NotCallable { .. } => diag.with_label("not a function"),
+            Self::ArityMismatch { expected, .. } => {
+                diag.with_label(format!("expected {expected} arguments"))
+            }
+            Self::StackOverflow { .. } => diag
+                .with_label("while calling this")
+                .with_note("this is usually caused by recursion that never ends"),
+            Self::InvalidCondition { ty, .. } => diag.with_label(format!("condition is `{ty}`")),
+            Self::MismatchedTypes { lhs, rhs, .. } => diag
+                .with_label(format!("`{lhs}` on the left, `{rhs}` on the right"))
+                .with_note("use `as` to convert one side to the other's type"),
+            Self::InvalidCast { from, .. } => diag.with_label(format!("value is `{from}`")),
+            Self::InvalidOperand { ty, .. } => diag.with_label(format!("operand is `{ty}`")),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DivisionByZero { .. } => write!(f, "attempt to divide by zero"),
+            Self::NegativeExponent { .. } => {
+                write!(f, "attempt to raise an integer to a negative power")
+            }
+            Self::Overflow { .. } => write!(f, "integer overflow"),
+            Self::UndefinedVariable { name, .. } => write!(f, "undefined variable `{name}`"),
+            Self::NotCallable { name, .. } => write!(f, "`{name}` is not a function"),
+            Self::ArityMismatch {
+                expected, found, ..
+            } => write!(f, "expected {expected} arguments, found {found}"),
+            Self::StackOverflow { .. } => write!(f, "stack overflow"),
+            Self::InvalidCondition { ty, .. } => {
+                write!(f, "a value of type `{ty}` can't be used as a condition")
+            }
+            Self::MismatchedTypes { op, lhs, rhs, .. } => {
+                write!(f, "cannot apply `{op}` to `{lhs}` and `{rhs}`")
+            }
+            Self::InvalidCast { from, to, .. } => write!(f, "cannot cast `{from}` to `{to}`"),
+            Self::InvalidOperand { op, ty, .. } => {
+                write!(f, "cannot apply `{op}` to a value of type `{ty}`")
+            }
+        }
+    }
+}
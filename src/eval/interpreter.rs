@@ -0,0 +1,387 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    ast::{
+        node::{
+            Assign, BinaryExpr, CallExpr, ForStmt, Ident, IfStmt, Let, Node, Return, UnaryExpr,
+            WhileStmt,
+        },
+        number::NumType,
+    },
+    lexer::token::Span,
+    trace,
+};
+
+use super::{
+    environment::Environment,
+    error::RuntimeError,
+    ops::{binary_op, cast, convert, logical_operand, unary_op},
+    value::Value,
+};
+
+type EvalResult = Result<Value, RuntimeError>;
+
+/// How deep function calls may nest before the program is stopped
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Stack size of the thread programs run on, enough for `MAX_CALL_DEPTH` nested calls in an
+/// unoptimised build
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Anything that stops evaluation early. Errors make it all the way out of the interpreter,
+/// `Return` is caught by the function call it belongs to and `Break`/`Continue` by their loop.
+enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Self {
+        Self::Error(e)
+    }
+}
+
+type ExecResult = Result<Value, Unwind>;
+
+/// Evaluates the AST by walking it directly
+pub struct Interpreter {
+    env: Environment,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            env: Environment::new(),
+            depth: 0usize,
+        }
+    }
+
+    /// Evaluates a top level statement
+    pub fn eval(&mut self, node: &Node) -> EvalResult {
+        match self.exec(node) {
+            Ok(value) => Ok(value),
+            // The checker only allows these inside of functions and loops
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Break | Unwind::Continue) => Ok(Value::Unit),
+            Err(Unwind::Error(e)) => Err(e),
+        }
+    }
+
+    fn exec(&mut self, node: &Node) -> ExecResult {
+        match node {
+            Node::Integer(v, ty) => Ok(Value::Integer(*v, *ty)),
+            Node::Number(v, ty) => Ok(Value::Number(*v, *ty)),
+            Node::Bool(v) => Ok(Value::Bool(*v)),
+            Node::Str(v) => Ok(Value::Str(v.clone())),
+            Node::Ident(ident) => {
+                self.env
+                    .get(&ident.name)
+                    .ok_or(Unwind::Error(RuntimeError::UndefinedVariable {
+                        name: ident.name.clone(),
+                        span: ident.span.clone(),
+                    }))
+            }
+            Node::Group(inner) => self.exec(inner),
+            Node::UnaryExpr(e) => self.exec_unary(e),
+            Node::BinaryExpr(e) => self.exec_binary(e),
+            Node::Cast(c) => {
+                let value = self.exec(&c.value)?;
+                // The checker only lets number types through
+                let ty = NumType::from_name(&c.ty.name).unwrap_or(NumType::I64);
+                Ok(cast(value, ty, &c.span)?)
+            }
+            Node::Let(l) => self.exec_let(l),
+            Node::Assign(a) => self.exec_assign(a),
+            Node::Function(func) => {
+                let name = &func.signature.name.name;
+                self.env.define(name, Value::Function(Rc::clone(func)));
+                Ok(Value::Unit)
+            }
+            Node::Call(c) => self.exec_call(c),
+            Node::Return(r) => self.exec_return(r),
+            Node::Block(body) => self.exec_block(body),
+            Node::If(stmt) => self.exec_if(stmt),
+            Node::While(stmt) => self.exec_while(stmt),
+            Node::For(stmt) => self.exec_for(stmt),
+            Node::Break(_) => Err(Unwind::Break),
+            Node::Continue(_) => Err(Unwind::Continue),
+        }
+    }
+
+    /// Runs the statements in a new scope which is removed again even if they unwind
+    fn exec_block(&mut self, body: &[Node]) -> ExecResult {
+        self.env.push_scope();
+        let result = body.iter().try_for_each(|node| self.exec(node).map(|_| ()));
+        self.env.pop_scope();
+        result.map(|_| Value::Unit)
+    }
+
+    fn exec_if(&mut self, stmt: &IfStmt) -> ExecResult {
+        for branch in &stmt.branches {
+            if self.condition(&branch.cond, &branch.span)? {
+                return self.exec_block(&branch.body);
+            }
+        }
+        match &stmt.otherwise {
+            Some(body) => self.exec_block(body),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn exec_while(&mut self, stmt: &WhileStmt) -> ExecResult {
+        while self.condition(&stmt.cond, &stmt.span)? {
+            match self.exec_block(&stmt.body) {
+                Ok(_) | Err(Unwind::Continue) => {}
+                Err(Unwind::Break) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    fn exec_for(&mut self, stmt: &ForStmt) -> ExecResult {
+        // The loop variable has the type of the start of the range, so the end has to fit in it
+        let (start, ty) = self.range_bound(&stmt.start, &stmt.span)?;
+        let (end, _) = self.range_bound(&stmt.end, &stmt.span)?;
+        let end = ty.fit(end).ok_or_else(|| RuntimeError::Overflow {
+            ty: ty.name(),
+            span: stmt.end_span.clone(),
+        })?;
+
+        for i in start..end {
+            // Every iteration gets its own copy of the loop variable
+            self.env.push_scope();
+            self.env.define(&stmt.var.name, Value::Integer(i, ty));
+            let result = self.exec_block(&stmt.body);
+            self.env.pop_scope();
+
+            match result {
+                Ok(_) | Err(Unwind::Continue) => {}
+                Err(Unwind::Break) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    /// Evaluates a condition, which has to be a `bool`
+    fn condition(&mut self, cond: &Node, span: &Span) -> Result<bool, Unwind> {
+        match self.exec(cond)? {
+            Value::Bool(v) => Ok(v),
+            v => Err(Unwind::Error(RuntimeError::InvalidCondition {
+                ty: v.type_name(),
+                span: span.clone(),
+            })),
+        }
+    }
+
+    fn range_bound(&mut self, node: &Node, span: &Span) -> Result<(i128, NumType), Unwind> {
+        match self.exec(node)? {
+            Value::Integer(v, ty) => Ok((v, ty)),
+            v => Err(Unwind::Error(RuntimeError::InvalidOperand {
+                op: "..",
+                ty: v.type_name(),
+                span: span.clone(),
+            })),
+        }
+    }
+
+    fn exec_let(&mut self, l: &Let) -> ExecResult {
+        let mut value = self.exec(&l.value)?;
+        if let Some(annotation) = &l.annotation {
+            value = annotate(value, annotation)?;
+        }
+        self.env.define(&l.name.name, value);
+        Ok(Value::Unit)
+    }
+
+    /// Mutability has already been checked, so this only has to find the variable
+    fn exec_assign(&mut self, a: &Assign) -> ExecResult {
+        let undefined = || RuntimeError::UndefinedVariable {
+            name: a.target.name.clone(),
+            span: a.target.span.clone(),
+        };
+
+        let rhs = self.exec(&a.value)?;
+        let current = self.env.get(&a.target.name).ok_or_else(undefined)?;
+        let value = match (a.op, current) {
+            // Numbers keep the type of the variable they are assigned to
+            (b'=', Value::Integer(_, ty) | Value::Number(_, ty)) => convert(rhs, ty, &a.span)?,
+            (b'=', _) => rhs,
+            (op, current) => binary_op(op, current, rhs, &a.span)?,
+        };
+
+        if self.env.assign(&a.target.name, value) {
+            Ok(Value::Unit)
+        } else {
+            Err(undefined().into())
+        }
+    }
+
+    fn exec_call(&mut self, c: &CallExpr) -> ExecResult {
+        let func = match self.env.get(&c.callee.name) {
+            Some(Value::Function(func)) => func,
+            Some(_) => {
+                return Err(Unwind::Error(RuntimeError::NotCallable {
+                    name: c.callee.name.clone(),
+                    span: c.callee.span.clone(),
+                }))
+            }
+            None => {
+                return Err(Unwind::Error(RuntimeError::UndefinedVariable {
+                    name: c.callee.name.clone(),
+                    span: c.callee.span.clone(),
+                }))
+            }
+        };
+
+        let params = &func.signature.params;
+        if params.len() != c.args.len() {
+            return Err(Unwind::Error(RuntimeError::ArityMismatch {
+                expected: params.len(),
+                found: c.args.len(),
+                span: c.span.clone(),
+            }));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(Unwind::Error(RuntimeError::StackOverflow {
+                span: c.span.clone(),
+            }));
+        }
+
+        trace!(
+            Eval,
+            Debug,
+            "call {} at depth {} ({})",
+            c.callee.name,
+            self.depth,
+            c.span
+        );
+
+        // Arguments are evaluated in the caller's scope
+        let mut args = Vec::with_capacity(c.args.len());
+        for arg in &c.args {
+            args.push(self.exec(arg)?);
+        }
+
+        let callee = Value::Function(Rc::clone(&func));
+        let saved = self.env.enter_function(&func.signature.name.name, callee);
+        self.depth += 1;
+        for (param, arg) in params.iter().zip(args) {
+            match annotate(arg, &param.annotation) {
+                Ok(arg) => self.env.define(&param.name.name, arg),
+                Err(e) => {
+                    self.depth -= 1;
+                    self.env.exit_function(saved);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let mut result = Ok(Value::Unit);
+        for node in &func.body {
+            if let Err(unwind) = self.exec(node) {
+                result = match unwind {
+                    Unwind::Return(value) => Ok(value),
+                    Unwind::Error(e) => Err(Unwind::Error(e)),
+                    // The checker keeps these from leaving the loop they are in
+                    Unwind::Break | Unwind::Continue => Ok(Value::Unit),
+                };
+                break;
+            }
+        }
+
+        self.depth -= 1;
+        self.env.exit_function(saved);
+        result
+    }
+
+    fn exec_return(&mut self, r: &Return) -> ExecResult {
+        let value = match &r.value {
+            Some(value) => self.exec(value)?,
+            None => Value::Unit,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn exec_unary(&mut self, e: &UnaryExpr) -> ExecResult {
+        // Taking the address of a variable shares its storage, anything else gets new storage
+        if e.op == b'&' {
+            if let Node::Ident(ident) = e.rhs.as_ref() {
+                if let Some(slot) = self.env.slot(&ident.name) {
+                    return Ok(Value::Pointer(slot));
+                }
+            }
+            let value = self.exec(&e.rhs)?;
+            return Ok(Value::Pointer(Rc::new(RefCell::new(value))));
+        }
+
+        let rhs = self.exec(&e.rhs)?;
+        Ok(unary_op(e.op, rhs, &e.span)?)
+    }
+
+    fn exec_binary(&mut self, e: &BinaryExpr) -> ExecResult {
+        let lhs = self.exec(&e.lhs)?;
+
+        // `and` and `or` only evaluate the RHS when the LHS doesn't decide the result
+        if matches!(e.op, b'&' | b'|') {
+            let lhs = logical_operand(e.op, lhs, &e.span)?;
+            if lhs == (e.op == b'|') {
+                return Ok(Value::Bool(lhs));
+            }
+            let rhs = self.exec(&e.rhs)?;
+            return Ok(Value::Bool(logical_operand(e.op, rhs, &e.span)?));
+        }
+
+        let rhs = self.exec(&e.rhs)?;
+        Ok(binary_op(e.op, lhs, rhs, &e.span)?)
+    }
+}
+
+/// Converts a value declared with a type annotation, like `new x :: u8 = 1`
+fn annotate(value: Value, annotation: &Ident) -> EvalResult {
+    match NumType::from_name(&annotation.name) {
+        Some(ty) => convert(value, ty, &annotation.span),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::ast::parse;
+
+    /// Runs `src` on a thread with the stack `main` gives programs and returns the value of the
+    /// last statement as text
+    fn run(src: &'static str) -> Result<String, RuntimeError> {
+        let worker = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let (_, ast) = parse(src);
+                let mut interpreter = Interpreter::new();
+                let mut last = Value::Unit;
+                for node in &ast {
+                    last = interpreter.eval(node)?;
+                }
+                Ok(last.to_string())
+            });
+        worker.expect("thread starts").join().expect("no panic")
+    }
+
+    #[test]
+    fn recursion_up_to_the_call_limit_runs() {
+        let src = "func f(n :: i64) -> i64 {\n    if n == 0 { return 0 }\n    return f(n - 1) + 1\n}\nf(999)\n";
+        assert_eq!(run(src).expect("no error"), "999");
+    }
+
+    #[test]
+    fn recursion_past_the_call_limit_is_an_error() {
+        let src = "func f(n :: i64) -> i64 {\n    return f(n + 1)\n}\nf(0)\n";
+        assert!(matches!(run(src), Err(RuntimeError::StackOverflow { .. })));
+    }
+}
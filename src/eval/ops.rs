@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+
+use crate::{
+    ast::{
+        node::{op_str, unary_op_str},
+        number::NumType,
+    },
+    lexer::token::Span,
+};
+
+use super::{error::RuntimeError, value::Value};
+
+type EvalResult = Result<Value, RuntimeError>;
+
+/// Applies a prefix operator other than `&`, which needs to know where its operand is stored
+pub fn unary_op(op: u8, value: Value, span: &Span) -> EvalResult {
+    match (op, value) {
+        (b'-', Value::Integer(v, ty)) => {
+            ty.fit(-v)
+                .map(|v| Value::Integer(v, ty))
+                .ok_or_else(|| RuntimeError::Overflow {
+                    ty: ty.name(),
+                    span: span.clone(),
+                })
+        }
+        (b'-', Value::Number(v, ty)) => Ok(Value::Number(-v, ty)),
+        (b'!', Value::Bool(v)) => Ok(Value::Bool(!v)),
+        (b'#', Value::Str(v)) => Ok(Value::Integer(v.chars().count() as i128, NumType::I64)),
+        (b'*', Value::Pointer(slot)) => Ok(slot.borrow().clone()),
+        (op, v) => Err(RuntimeError::InvalidOperand {
+            op: unary_op_str(op),
+            ty: v.type_name(),
+            span: span.clone(),
+        }),
+    }
+}
+
+/// Both sides of `and` and `or` have to be `bool`s
+pub fn logical_operand(op: u8, value: Value, span: &Span) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(v) => Ok(v),
+        v => Err(RuntimeError::InvalidOperand {
+            op: op_str(op),
+            ty: v.type_name(),
+            span: span.clone(),
+        }),
+    }
+}
+
+/// Integer operands stay integers, if either side is a float both are promoted to floats.
+/// Both sides must have the same type, except that `i64` and `f64`, the types of literals without
+/// a suffix, give way to the other side so `x + 1` works whatever the type of `x` is.
+pub fn binary_op(op: u8, lhs: Value, rhs: Value, span: &Span) -> EvalResult {
+    if is_comparison(op) {
+        return compare(op, &lhs, &rhs, span);
+    }
+
+    match (&lhs, &rhs) {
+        (Value::Integer(a, lt), Value::Integer(b, rt)) => {
+            let ty = common_type(op, *lt, *rt, span)?;
+            return integer_op(op, *a, *b, ty, span);
+        }
+        (Value::Str(a), Value::Str(b)) if op == b'+' => return Ok(Value::Str(format!("{a}{b}"))),
+        _ => {}
+    }
+
+    let ty = match (&lhs, &rhs) {
+        (Value::Number(_, lt), Value::Number(_, rt)) => common_type(op, *lt, *rt, span)?,
+        (Value::Number(_, ty), _) | (_, Value::Number(_, ty)) => *ty,
+        _ => NumType::F64,
+    };
+    match (lhs.as_number(), rhs.as_number()) {
+        (Some(a), Some(b)) => Ok(Value::Number(ty.round(float_op(op, a, b)), ty)),
+        _ => Err(invalid_operands(op, &lhs, &rhs, span)),
+    }
+}
+
+/// The type two numbers of the same kind are operated on as, see `binary_op`
+fn common_type(op: u8, lhs: NumType, rhs: NumType, span: &Span) -> Result<NumType, RuntimeError> {
+    match (lhs, rhs) {
+        _ if lhs == rhs => Ok(lhs),
+        (NumType::I64 | NumType::F64, ty) | (ty, NumType::I64 | NumType::F64) => Ok(ty),
+        _ => Err(RuntimeError::MismatchedTypes {
+            op: op_str(op),
+            lhs: lhs.name(),
+            rhs: rhs.name(),
+            span: span.clone(),
+        }),
+    }
+}
+
+fn is_comparison(op: u8) -> bool {
+    matches!(op, b'=' | b'!' | b'<' | b'L' | b'>' | b'G')
+}
+
+/// Numbers can be compared with each other and strings are ordered by their characters, `bool`s
+/// can only be checked for equality
+fn compare(op: u8, lhs: &Value, rhs: &Value, span: &Span) -> EvalResult {
+    let ordering = match (lhs, rhs) {
+        // Integers of different types can still be compared exactly
+        (Value::Integer(a, _), Value::Integer(b, _)) => a.partial_cmp(b),
+        (Value::Str(a), Value::Str(b)) => a.partial_cmp(b),
+        (Value::Bool(a), Value::Bool(b)) if matches!(op, b'=' | b'!') => a.partial_cmp(b),
+        _ => match (lhs.as_number(), rhs.as_number()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => return Err(invalid_operands(op, lhs, rhs, span)),
+        },
+    };
+
+    // `ordering` is `None` when a float is NaN, which isn't equal to or ordered with anything
+    let result = match op {
+        b'=' => ordering == Some(Ordering::Equal),
+        b'!' => ordering != Some(Ordering::Equal),
+        b'<' => ordering == Some(Ordering::Less),
+        b'L' => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        b'>' => ordering == Some(Ordering::Greater),
+        b'G' => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        _ => unreachable!("unknown comparison operator `{}`", op_str(op)),
+    };
+    Ok(Value::Bool(result))
+}
+
+/// Reports whichever operand doesn't fit the operator, preferring the LHS. A string next to
+/// anything else blames the other side, since `"a" + 1` is more likely a missing string.
+fn invalid_operands(op: u8, lhs: &Value, rhs: &Value, span: &Span) -> RuntimeError {
+    let bad = match (lhs, rhs) {
+        (Value::Str(_), rhs) if op == b'+' || is_comparison(op) => rhs,
+        _ if lhs.as_number().is_none() => lhs,
+        _ => rhs,
+    };
+    RuntimeError::InvalidOperand {
+        op: op_str(op),
+        ty: bad.type_name(),
+        span: span.clone(),
+    }
+}
+
+fn integer_op(op: u8, a: i128, b: i128, ty: NumType, span: &Span) -> EvalResult {
+    let overflow = || RuntimeError::Overflow {
+        ty: ty.name(),
+        span: span.clone(),
+    };
+
+    // Division by zero has to be caught before `checked_*` reports it as an overflow
+    if matches!(op, b'/' | b'%') && b == 0 {
+        return Err(RuntimeError::DivisionByZero { span: span.clone() });
+    }
+
+    // Every operand fits in an `i128`, so overflow is checked against `ty` afterwards
+    let result = match op {
+        b'+' => a.checked_add(b),
+        b'-' => a.checked_sub(b),
+        b'*' => a.checked_mul(b),
+        b'/' => a.checked_div(b),
+        b'%' => a.checked_rem(b),
+        // The result has to keep the integer type, which a negative exponent can't give
+        b'^' if b < 0 => return Err(RuntimeError::NegativeExponent { span: span.clone() }),
+        b'^' => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+        _ => unreachable!("unknown binary operator `{}`", op_str(op)),
+    };
+    result
+        .and_then(|v| ty.fit(v))
+        .map(|v| Value::Integer(v, ty))
+        .ok_or_else(overflow)
+}
+
+fn float_op(op: u8, a: f64, b: f64) -> f64 {
+    match op {
+        b'+' => a + b,
+        b'-' => a - b,
+        b'*' => a * b,
+        b'/' => a / b,
+        b'%' => a % b,
+        b'^' => a.powf(b),
+        _ => unreachable!("unknown binary operator `{}`", op_str(op)),
+    }
+}
+
+/// Converts a number to `ty` for `as`, failing if it doesn't fit. Floats are truncated towards
+/// zero when cast to integers.
+pub fn cast(value: Value, ty: NumType, span: &Span) -> EvalResult {
+    let overflow = || RuntimeError::Overflow {
+        ty: ty.name(),
+        span: span.clone(),
+    };
+
+    match value {
+        Value::Integer(v, _) if ty.is_float() => Ok(Value::Number(ty.round(v as f64), ty)),
+        Value::Number(v, _) if ty.is_float() => Ok(Value::Number(ty.round(v), ty)),
+        Value::Integer(v, _) => ty
+            .fit(v)
+            .map(|v| Value::Integer(v, ty))
+            .ok_or_else(overflow),
+        // `as i128` saturates, which is out of range for every type anyway
+        Value::Number(v, _) if v.is_finite() => ty
+            .fit(v.trunc() as i128)
+            .map(|v| Value::Integer(v, ty))
+            .ok_or_else(overflow),
+        Value::Number(..) => Err(overflow()),
+        v => Err(RuntimeError::InvalidCast {
+            from: v.type_name(),
+            to: ty.name(),
+            span: span.clone(),
+        }),
+    }
+}
+
+/// Implicitly converts a number to `ty` when it is stored somewhere of that type. Unlike `cast`
+/// floats are never turned into integers, anything that can't be converted is left as it is.
+pub fn convert(value: Value, ty: NumType, span: &Span) -> EvalResult {
+    match value {
+        Value::Integer(..) => cast(value, ty, span),
+        Value::Number(..) if ty.is_float() => cast(value, ty, span),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_integer_exponent_fails() {
+        let span = Span::from(0, 1);
+        let two = Value::Integer(2, NumType::I64);
+        let result = binary_op(b'^', two.clone(), Value::Integer(-1, NumType::I64), &span);
+        assert!(matches!(result, Err(RuntimeError::NegativeExponent { .. })));
+
+        let result = binary_op(b'^', two, Value::Integer(10, NumType::I64), &span);
+        assert!(matches!(result, Ok(Value::Integer(1024, NumType::I64))));
+        let result = binary_op(
+            b'^',
+            Value::Number(2.0, NumType::F64),
+            Value::Integer(-1, NumType::I64),
+            &span,
+        );
+        assert!(matches!(result, Ok(Value::Number(v, NumType::F64)) if v == 0.5));
+    }
+}
@@ -0,0 +1,67 @@
+use std::{fmt, rc::Rc};
+
+use crate::{
+    ast::{node::FunctionExpr, number::NumType},
+    compile::chunk::Function,
+};
+
+use super::environment::Slot;
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// Every integer type fits in an `i128`, the type says which range the value must stay in
+    Integer(i128, NumType),
+    /// `f32`s are stored rounded to `f32` precision
+    Number(f64, NumType),
+    Bool(bool),
+    Str(String),
+    Function(Rc<FunctionExpr>),
+    /// A function compiled to bytecode for the VM
+    Compiled(Rc<Function>),
+    /// Made by `&`, shares the storage of whatever it points to
+    Pointer(Slot),
+    /// Produced by statements such as declarations that don't have a value
+    Unit,
+}
+
+impl Value {
+    /// Returns the value as a float, integers are promoted
+    pub fn as_number(&self) -> Option<f64> {
+        match *self {
+            Self::Integer(v, _) => Some(v as f64),
+            Self::Number(v, _) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Name of the value's type, used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_, ty) | Self::Number(_, ty) => ty.name(),
+            Self::Bool(_) => "bool",
+            Self::Str(_) => "str",
+            Self::Function(_) | Self::Compiled(_) => "func",
+            Self::Pointer(_) => "pointer",
+            Self::Unit => "()",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(v, _) => write!(f, "{v}"),
+            // Debug formatting keeps the `.0` on whole floats so they don't look like integers,
+            // `f32`s are printed as `f32`s so they don't show the digits lost to rounding
+            Self::Number(v, NumType::F32) => write!(f, "{:?}", *v as f32),
+            Self::Number(v, _) => write!(f, "{v:?}"),
+            Self::Bool(v) => write!(f, "{v}"),
+            Self::Str(v) => write!(f, "{v}"),
+            Self::Function(func) => write!(f, "<func {}>", func.signature.name.name),
+            Self::Compiled(func) => write!(f, "<func {}>", func.name),
+            Self::Pointer(slot) => write!(f, "&{}", slot.borrow()),
+            Self::Unit => write!(f, "()"),
+        }
+    }
+}
@@ -0,0 +1,773 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ast::node::{op_str, unary_op_str, BinaryExpr, CallExpr, FunctionExpr, Node},
+    lexer::{
+        source::SourceFile,
+        token::{Comment, CommentKind, Span, Token, TokenKind},
+    },
+    serialize::tree::{ToTree, Tree},
+};
+
+/// Lines longer than this have their argument and parameter lists split one item per line, and
+/// chains of binary operators broken after an operator
+pub const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+/// Prints the AST back out as canonical source. The AST doesn't keep comments or how literals
+/// were written, so those are taken from the tokens the AST was parsed from:
+///
+/// - comments are written in front of the token they came before. Ones before a statement go on
+///   their own line, or at the end of the line they were on if they followed code.
+/// - literals are printed as written, the literal tokens appear in the same order as the
+///   literal nodes when the tree is walked left to right
+/// - `{` and `}` positions tell which comments belong inside a block, `(` and `)` positions
+///   where the parentheses of a group are
+pub struct Formatter<'a> {
+    file: &'a SourceFile,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    literals: Vec<Span>,
+    next_literal: usize,
+    /// Start of every `{` mapped to the start of the `}` closing it
+    braces: BTreeMap<usize, usize>,
+    /// The same for `(` and `)`
+    parens: BTreeMap<usize, usize>,
+    /// Start of every `else`, which the AST has no position for
+    elses: BTreeSet<usize>,
+    out: String,
+    indent: usize,
+    /// Furthest source offset written so far
+    cursor: usize,
+    /// Source line of the last thing written, used to keep blank lines and trailing comments
+    last_line: Option<usize>,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(file: &'a SourceFile, tokens: &[Token]) -> Self {
+        let braces = pairs(tokens, TokenKind::LCurl, TokenKind::RCurl);
+        let parens = pairs(tokens, TokenKind::LPar, TokenKind::RPar);
+
+        Self {
+            file,
+            comments: tokens
+                .iter()
+                .flat_map(|t| t.trivia.iter().cloned())
+                .collect(),
+            next_comment: 0usize,
+            literals: tokens
+                .iter()
+                .filter(|t| matches!(t.kind, TokenKind::Number { .. } | TokenKind::Literal { .. }))
+                .map(|t| t.span.clone())
+                .collect(),
+            next_literal: 0usize,
+            braces,
+            parens,
+            elses: tokens
+                .iter()
+                .filter(|t| t.kind == TokenKind::Else)
+                .map(|t| t.span.start())
+                .collect(),
+            out: String::new(),
+            indent: 0usize,
+            cursor: 0usize,
+            last_line: None,
+        }
+    }
+
+    pub fn format(mut self, ast: &[Node]) -> String {
+        for node in ast {
+            self.statement(node);
+        }
+        self.flush_comments(usize::MAX);
+        self.out
+    }
+
+    fn statement(&mut self, node: &Node) {
+        let start = self.start(node).unwrap_or(self.cursor);
+        self.flush_comments(start);
+        self.separate(self.line(start));
+        self.out.push_str(&INDENT.repeat(self.indent));
+
+        match node {
+            Node::Function(func) => self.function(func),
+            Node::Block(body) => {
+                self.block(body, self.cursor);
+            }
+            Node::If(stmt) => {
+                let mut after = 0usize;
+                for (i, branch) in stmt.branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    if i > 0 {
+                        self.after_block(branch.span.start());
+                    }
+                    let col = self.column() + keyword.len() + 1;
+                    let cond = self.expr(&branch.cond, Some(col));
+                    self.out.push_str(&format!("{keyword} {cond} "));
+                    after = self.block(&branch.body, branch.span.start());
+                }
+                if let Some(body) = &stmt.otherwise {
+                    let keyword = self.elses.range(after..).next().copied();
+                    self.after_block(keyword.unwrap_or(after));
+                    self.out.push_str("else ");
+                    self.block(body, after + 1);
+                }
+            }
+            Node::While(stmt) => {
+                let cond = self.fit(8, &stmt.cond);
+                self.out.push_str(&format!("while {cond} "));
+                self.block(&stmt.body, stmt.span.start());
+            }
+            Node::For(stmt) => {
+                let start = self.expr(&stmt.start, None);
+                let end = self.expr(&stmt.end, None);
+                self.out
+                    .push_str(&format!("for {} in {start}..{end} ", stmt.var.name));
+                self.block(&stmt.body, stmt.span.start());
+            }
+            node => {
+                let line = self.simple_statement(node);
+                self.out.push_str(&line);
+            }
+        }
+        self.out.push('\n');
+
+        // Literals like `true` have no position, a statement ending in one ends where it started
+        let end = self.end(node).unwrap_or(start);
+        self.cursor = self.cursor.max(end);
+        self.last_line = Some(self.line(self.cursor));
+    }
+
+    /// Statements that fit on one line unless a call or operator chain in them is too long
+    fn simple_statement(&mut self, node: &Node) -> String {
+        match node {
+            Node::Let(l) => {
+                let mut head = String::from("new ");
+                if l.mutable {
+                    head.push_str("mut ");
+                }
+                head.push_str(&l.name.name);
+                if let Some(ty) = &l.annotation {
+                    head.push_str(&format!(" :: {}", ty.name));
+                }
+                head.push_str(" = ");
+                let value = self.fit(head.len(), &l.value);
+                head + &value
+            }
+            Node::Assign(a) => {
+                let head = match a.op {
+                    b'=' => format!("{} = ", a.target.name),
+                    op => format!("{} {}= ", a.target.name, op_str(op)),
+                };
+                let value = self.fit(head.len(), &a.value);
+                head + &value
+            }
+            Node::Return(r) => match &r.value {
+                Some(value) => format!("return {}", self.fit(7, value)),
+                None => "return".to_string(),
+            },
+            Node::Break(_) => "break".to_string(),
+            Node::Continue(_) => "continue".to_string(),
+            node => self.fit(0, node),
+        }
+    }
+
+    fn function(&mut self, func: &FunctionExpr) {
+        let sig = &func.signature;
+        let params: Vec<String> = sig
+            .params
+            .iter()
+            .map(|p| {
+                let comments = self.before(p.name.span.start());
+                let prefix = if p.mutable { "mut " } else { "" };
+                let ty = self.before(p.annotation.span.start());
+                format!(
+                    "{comments}{prefix}{} :: {ty}{}",
+                    p.name.name, p.annotation.name
+                )
+            })
+            .collect();
+        let returns = match &sig.returns {
+            Some(ty) => format!(" -> {}{}", self.before(ty.span.start()), ty.name),
+            None => String::new(),
+        };
+
+        let flat = format!("func {}({}){returns} {{", sig.name.name, params.join(", "));
+        let fits = self.width(&flat) <= MAX_WIDTH && !flat.contains('\n');
+        if fits || params.is_empty() {
+            self.out.push_str(&flat[..flat.len() - 1]);
+        } else {
+            let pad = INDENT.repeat(self.indent + 1);
+            self.out.push_str(&format!("func {}(\n", sig.name.name));
+            for param in &params {
+                self.out.push_str(&format!("{pad}{param},\n"));
+            }
+            let close = INDENT.repeat(self.indent);
+            self.out.push_str(&format!("{close}){returns} "));
+        }
+        self.block(&func.body, sig.name.span.start());
+    }
+
+    /// Writes a block whose `{` is the first one at or after `after`, returns where its `}` is
+    fn block(&mut self, body: &[Node], after: usize) -> usize {
+        let braces = self.braces.range(after..).next().map(|(o, c)| (*o, *c));
+        let Some((open, close)) = braces else {
+            // Only reachable if the tokens don't match the AST, fall back to losing comments
+            self.out.push_str("{ }");
+            return after;
+        };
+
+        let comments = self.inline_comments(open, self.indent);
+        self.out.push_str(&comments);
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start() < close);
+        if body.is_empty() && !has_comments {
+            self.out.push_str("{}");
+        } else {
+            self.out.push_str("{\n");
+            self.indent += 1;
+            self.cursor = open + 1;
+            self.last_line = Some(self.line(open));
+            for node in body {
+                self.statement(node);
+            }
+            self.flush_comments(close);
+            self.indent -= 1;
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.out.push('}');
+        }
+
+        self.cursor = close;
+        self.last_line = Some(self.line(close));
+        close
+    }
+
+    /// Writes the comments that start before `offset` on lines of their own
+    fn flush_comments(&mut self, offset: usize) {
+        for comment in self.take_comments(offset) {
+            let line = self.line(comment.span.start());
+
+            // A comment on the same line as the code before it stays at the end of that line
+            if self.last_line == Some(line) && self.out.ends_with('\n') {
+                self.out.pop();
+                self.out.push(' ');
+            } else {
+                self.separate(line);
+                self.out.push_str(&INDENT.repeat(self.indent));
+            }
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+            self.last_line = Some(self.line(comment.span.end()));
+        }
+    }
+
+    /// Writes the comments before the keyword at `offset` that continues a statement after a `}`,
+    /// and the space before the keyword
+    fn after_block(&mut self, offset: usize) {
+        let mut line_start = false;
+        for comment in self.take_comments(offset) {
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+            line_start = comment.kind != CommentKind::Block;
+            if line_start {
+                self.out.push('\n');
+                self.out.push_str(&INDENT.repeat(self.indent));
+            }
+        }
+        if !line_start {
+            self.out.push(' ');
+        }
+    }
+
+    /// Comments in front of a token inside an expression, which continues at one level deeper
+    /// indentation after a line comment
+    fn before(&mut self, offset: usize) -> String {
+        self.inline_comments(offset, self.indent + 1)
+    }
+
+    /// Comments before the token at `offset`, to be written in front of it on the same line.
+    /// The token goes on the next line at `indent` after a line comment.
+    fn inline_comments(&mut self, offset: usize, indent: usize) -> String {
+        let mut out = String::new();
+        for comment in self.take_comments(offset) {
+            out.push_str(&comment.text);
+            match comment.kind {
+                CommentKind::Block => out.push(' '),
+                CommentKind::Line | CommentKind::Doc => {
+                    out.push('\n');
+                    out.push_str(&INDENT.repeat(indent));
+                }
+            }
+        }
+        out
+    }
+
+    /// Comments before a closing token, to be written after whatever comes before it
+    fn closing_comments(&mut self, offset: usize) -> String {
+        let mut out = String::new();
+        for comment in self.take_comments(offset) {
+            out.push(' ');
+            out.push_str(&comment.text);
+            if comment.kind != CommentKind::Block {
+                out.push('\n');
+                out.push_str(&INDENT.repeat(self.indent));
+            }
+        }
+        out
+    }
+
+    /// Takes the comments that start before `offset` and haven't been written yet
+    fn take_comments(&mut self, offset: usize) -> Vec<Comment> {
+        let start = self.next_comment;
+        while self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start() < offset)
+        {
+            self.next_comment += 1;
+        }
+        self.comments[start..self.next_comment].to_vec()
+    }
+
+    /// How far through the literals and comments the formatter is, to go back to when an
+    /// expression is formatted again with a different layout
+    fn mark(&self) -> (usize, usize) {
+        (self.next_literal, self.next_comment)
+    }
+
+    fn rewind(&mut self, (literal, comment): (usize, usize)) {
+        self.next_literal = literal;
+        self.next_comment = comment;
+    }
+
+    /// Keeps one blank line where the source had any, except at the start of a block or file
+    fn separate(&mut self, line: usize) {
+        let gap = self.last_line.is_some_and(|last| line > last + 1);
+        if gap && !self.out.is_empty() && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Formats an expression that comes after `used` characters of the current line
+    fn fit(&mut self, used: usize, node: &Node) -> String {
+        self.expr(node, Some(INDENT.len() * self.indent + used))
+    }
+
+    /// Formats an expression starting at column `col`, splitting calls and operator chains that go
+    /// past `MAX_WIDTH` over several lines. `None` keeps it all on one line.
+    fn expr(&mut self, node: &Node, col: Option<usize>) -> String {
+        match node {
+            Node::Integer(v, ty) => {
+                let sign = if *v < 0 { "-" } else { "" };
+                self.literal(sign, || format!("{v}{}", suffix(ty.name(), "i64")))
+            }
+            Node::Number(v, ty) => {
+                self.literal("", || format!("{v:?}{}", suffix(ty.name(), "f64")))
+            }
+            Node::Str(v) => self.literal("", || format!("{v:?}")),
+            Node::Bool(v) => v.to_string(),
+            Node::Ident(ident) => self.before(ident.span.start()) + &ident.name,
+            Node::Group(inner) => {
+                let parens = self.parens(inner);
+                let open = parens.map_or(String::new(), |(open, _)| self.before(open));
+                let inner = self.expr(inner, col.map(|c| c + 1));
+                let close = parens.map_or(String::new(), |(_, close)| self.closing_comments(close));
+                format!("{open}({inner}{close})")
+            }
+            Node::UnaryExpr(e) => {
+                let comments = self.before(e.span.start());
+                // `!` binds tighter than any binary operator, so an operand that is one came from
+                // `not`, which has to stay a word to keep the same meaning
+                let spelled_not = &self.file.src()[e.span.start()..=e.span.end()] == "not";
+                let op = match (e.op, e.rhs.as_ref()) {
+                    (b'!', _) if spelled_not => "not ",
+                    (b'!', Node::BinaryExpr(_) | Node::Cast(_)) => "not ",
+                    (op, _) => unary_op_str(op),
+                };
+                let rhs = self.expr(&e.rhs, col.map(|c| c + op.len()));
+                format!("{comments}{op}{rhs}")
+            }
+            Node::BinaryExpr(e) => self.binary(e, col),
+            Node::Cast(c) => {
+                let value = self.expr(&c.value, col);
+                let keyword = self.before(c.span.start());
+                let ty = self.before(c.ty.span.start());
+                format!("{value} {keyword}as {ty}{}", c.ty.name)
+            }
+            Node::Call(c) => self.call(c, col),
+            // Statements can't appear inside of expressions
+            node => node.to_string(),
+        }
+    }
+
+    /// Too long chains of operators that bind equally tightly are broken after as few operators
+    /// as possible, the lines after the first one indented one level deeper
+    fn binary(&mut self, e: &BinaryExpr, col: Option<usize>) -> String {
+        let mark = self.mark();
+        let lhs = self.expr(&e.lhs, None);
+        let op = self.before(e.span.start()) + op_str(e.op);
+        let rhs = self.expr(&e.rhs, None);
+        let flat = format!("{lhs} {op} {rhs}");
+        let Some(col) = col.filter(|col| column_after(*col, &flat) > MAX_WIDTH) else {
+            return flat;
+        };
+
+        self.rewind(mark);
+        let (first, rest) = chain(e);
+        let mut out = self.expr(first, Some(col));
+        let mut line = column_after(col, &out);
+        let pad = INDENT.repeat(self.indent + 1);
+        for (i, operand) in rest.iter().enumerate() {
+            let mark = self.mark();
+            let op = self.before(operand.span.start()) + op_str(operand.op);
+            let text = format!(" {op} {}", self.expr(&operand.rhs, None));
+            // Room is left for the operator that a break after this operand would end the line with
+            let next = rest.get(i + 1).map_or(0, |next| op_str(next.op).len() + 1);
+            if column_after(line, &text) + next <= MAX_WIDTH {
+                line = column_after(line, &text);
+                out.push_str(&text);
+                continue;
+            }
+
+            self.rewind(mark);
+            self.indent += 1;
+            let op = self.before(operand.span.start()) + op_str(operand.op);
+            let text = self.expr(&operand.rhs, Some(pad.len()));
+            self.indent -= 1;
+            out.push_str(&format!(" {op}\n{pad}{text}"));
+            line = column_after(pad.len(), &text);
+        }
+        out
+    }
+
+    fn call(&mut self, c: &CallExpr, col: Option<usize>) -> String {
+        let callee = self.before(c.callee.span.start()) + &c.callee.name;
+        let mark = self.mark();
+        let args: Vec<String> = c.args.iter().map(|a| self.expr(a, None)).collect();
+        let close = self.closing_comments(c.span.end());
+        let flat = format!("{callee}({}{close})", args.join(", "));
+        // A line comment inside the call ends a line, which only fits between arguments
+        let fits =
+            !flat.contains('\n') && col.is_none_or(|col| col + flat.chars().count() <= MAX_WIDTH);
+        if fits || c.args.is_empty() {
+            return flat;
+        }
+
+        // Too long, so each argument goes on its own line and is split again if it still is.
+        // Comments go on lines of their own in front of the argument they came before, or after
+        // the argument before them if they were on the same line as it.
+        self.rewind(mark);
+        self.indent += 1;
+        let pad = INDENT.repeat(self.indent);
+        let mut args = String::new();
+        let mut last_line = self.line(c.callee.span.start());
+        for arg in &c.args {
+            if let Some(start) = self.start(arg) {
+                self.comment_lines(start, last_line, &pad, &mut args);
+            }
+            let text = self.fit(0, arg);
+            args.push_str(&format!("{pad}{text},\n"));
+            if let Some(end) = self.end(arg) {
+                last_line = self.line(end);
+            }
+        }
+        self.comment_lines(c.span.end(), last_line, &pad, &mut args);
+        self.indent -= 1;
+        format!("{callee}(\n{args}{})", INDENT.repeat(self.indent))
+    }
+
+    /// Adds the comments before `offset` to a list of lines, ones that started on `last_line`
+    /// go at the end of the last line instead of on their own
+    fn comment_lines(&mut self, offset: usize, last_line: usize, pad: &str, out: &mut String) {
+        for comment in self.take_comments(offset) {
+            if self.line(comment.span.start()) == last_line && out.ends_with('\n') {
+                out.pop();
+                out.push(' ');
+            } else {
+                out.push_str(pad);
+            }
+            out.push_str(&comment.text);
+            out.push('\n');
+        }
+    }
+
+    /// Takes the next literal token's text after `sign`, or formats the value if the tokens ran
+    /// out. The parser folds `-` into integers that only fit negated, their token lacks the `-`.
+    fn literal(&mut self, sign: &str, fallback: impl FnOnce() -> String) -> String {
+        match self.literals.get(self.next_literal).cloned() {
+            Some(span) => {
+                self.next_literal += 1;
+                self.before(span.start()) + sign + &self.file.src()[span.start()..=span.end()]
+            }
+            None => fallback(),
+        }
+    }
+
+    /// Where the parentheses around a group are
+    fn parens(&self, inner: &Node) -> Option<(usize, usize)> {
+        // Nothing comes between the `(` and the start of what is inside it
+        let start = self.start(inner)?;
+        let (open, close) = self.parens.range(..start).next_back()?;
+        Some((*open, *close))
+    }
+
+    /// Source offset of the first thing in a statement that has a known position
+    fn start(&self, node: &Node) -> Option<usize> {
+        match node {
+            Node::Integer(..) | Node::Number(..) | Node::Str(_) => {
+                self.literals.get(self.next_literal).map(Span::start)
+            }
+            Node::Bool(_) => None,
+            // Blocks have no span of their own, the next `{` is where one starts
+            Node::Block(_) => self
+                .braces
+                .range(self.cursor..)
+                .next()
+                .map(|(open, _)| *open),
+            Node::Ident(ident) => Some(ident.span.start()),
+            Node::Group(inner) => self.parens(inner).map(|(open, _)| open),
+            Node::UnaryExpr(e) => Some(e.span.start()),
+            Node::BinaryExpr(e) => self.start(&e.lhs).or(Some(e.span.start())),
+            Node::Cast(c) => self.start(&c.value).or(Some(c.span.start())),
+            Node::Let(l) => Some(l.name.span.start()),
+            Node::Assign(a) => Some(a.target.span.start()),
+            Node::Function(func) => Some(func.signature.name.span.start()),
+            Node::Call(c) => Some(c.callee.span.start()),
+            Node::Return(r) => Some(r.span.start()),
+            Node::If(stmt) => stmt.branches.first().map(|b| b.span.start()),
+            Node::While(stmt) => Some(stmt.span.start()),
+            Node::For(stmt) => Some(stmt.var.span.start()),
+            Node::Break(span) | Node::Continue(span) => Some(span.start()),
+        }
+    }
+
+    /// Source offset of the last thing in a statement that was just written, statements ending
+    /// in a block have already moved the cursor to their `}`
+    fn end(&self, node: &Node) -> Option<usize> {
+        match node {
+            Node::Integer(..) | Node::Number(..) | Node::Str(_) => self
+                .next_literal
+                .checked_sub(1)
+                .and_then(|i| self.literals.get(i))
+                .map(Span::end),
+            Node::Ident(ident) => Some(ident.span.end()),
+            Node::Group(inner) => self.parens(inner).map(|(_, close)| close),
+            Node::UnaryExpr(e) => self.end(&e.rhs),
+            Node::BinaryExpr(e) => self.end(&e.rhs),
+            Node::Cast(c) => Some(c.ty.span.end()),
+            Node::Let(l) => self.end(&l.value),
+            Node::Assign(a) => self.end(&a.value),
+            Node::Call(c) => Some(c.span.end()),
+            Node::Return(r) => match &r.value {
+                Some(value) => self.end(value),
+                None => Some(r.span.end()),
+            },
+            Node::Break(span) | Node::Continue(span) => Some(span.end()),
+            Node::Bool(_)
+            | Node::Function(_)
+            | Node::Block(_)
+            | Node::If(_)
+            | Node::While(_)
+            | Node::For(_) => None,
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.file.position(offset).line
+    }
+
+    /// Column the next character written to the output goes in
+    fn column(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or_default();
+        line.chars().count()
+    }
+
+    /// Width in characters of `text` once it is written at the current indentation
+    fn width(&self, text: &str) -> usize {
+        INDENT.len() * self.indent + text.chars().count()
+    }
+}
+
+/// The first operand of a chain like `a + b - c`, which the parser nests to the left, and the
+/// expressions that apply each operator after it, in order
+fn chain(e: &BinaryExpr) -> (&Node, Vec<&BinaryExpr>) {
+    let mut rest = vec![e];
+    let mut first = e.lhs.as_ref();
+    while let Node::BinaryExpr(inner) = first {
+        if !same_level(inner.op, e.op) {
+            break;
+        }
+        rest.push(inner);
+        first = inner.lhs.as_ref();
+    }
+    rest.reverse();
+    (first, rest)
+}
+
+/// Whether two left associative operators bind equally tightly
+fn same_level(a: u8, b: u8) -> bool {
+    let level = |op| match op {
+        b'+' | b'-' => 1,
+        b'*' | b'/' | b'%' => 2,
+        b'&' => 3,
+        b'|' => 4,
+        _ => 0,
+    };
+    level(a) != 0 && level(a) == level(b)
+}
+
+/// Column the cursor is at after writing `text` starting at column `col`
+fn column_after(col: usize, text: &str) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last)) => last.chars().count(),
+        None => col + text.chars().count(),
+    }
+}
+
+/// Maps the start of every `open` token to the start of the `close` token matching it
+fn pairs(tokens: &[Token], open: TokenKind, close: TokenKind) -> BTreeMap<usize, usize> {
+    let mut starts = Vec::new();
+    let mut pairs = BTreeMap::new();
+    for token in tokens {
+        if token.kind == open {
+            starts.push(token.span.start());
+        } else if token.kind == close {
+            if let Some(start) = starts.pop() {
+                pairs.insert(start, token.span.start());
+            }
+        }
+    }
+    pairs
+}
+
+/// Suffix a literal needs to keep its type when it isn't the default one
+fn suffix(name: &'static str, default: &str) -> &'static str {
+    if name == default {
+        ""
+    } else {
+        name
+    }
+}
+
+/// Whether two parses are the same program, ignoring where anything was in the source
+pub fn same_program(a: &[Node], b: &[Node]) -> bool {
+    strip_spans(a.to_tree()) == strip_spans(b.to_tree())
+}
+
+fn strip_spans(tree: Tree) -> Tree {
+    match tree {
+        Tree::List(items) => Tree::List(items.into_iter().map(strip_spans).collect()),
+        Tree::Object(fields) => Tree::Object(
+            fields
+                .into_iter()
+                .filter(|(name, _)| *name != "span")
+                .map(|(name, value)| (name, strip_spans(value)))
+                .collect(),
+        ),
+        tree => tree,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    /// Formats `src`, checking the result means the same and formats to itself
+    fn format(src: &str) -> String {
+        let (tokens, ast) = parse(src);
+        let file = SourceFile::new("test", src.to_string());
+        let formatted = Formatter::new(&file, &tokens).format(&ast);
+
+        let (tokens, reparsed) = parse(&formatted);
+        assert!(
+            same_program(&ast, &reparsed),
+            "meaning changed:\n{formatted}"
+        );
+        let file = SourceFile::new("test", formatted.clone());
+        let again = Formatter::new(&file, &tokens).format(&reparsed);
+        assert_eq!(formatted, again, "not idempotent");
+        formatted
+    }
+
+    #[test]
+    fn normalises_spacing_and_indentation() {
+        let src = "new   mut x::i64=1+2\nfunc f( a::i64 ,mut b::i64 )->i64{\nif a<b{return a}\n  return b}\n";
+        let expected = "new mut x :: i64 = 1 + 2\nfunc f(a :: i64, mut b :: i64) -> i64 {\n    if a < b {\n        return a\n    }\n    return b\n}\n";
+        assert_eq!(format(src), expected);
+    }
+
+    #[test]
+    fn keeps_literals_as_written() {
+        let src = "new a = 0xFF + 0b1010 + 0o17 + 1_000_000\nnew b = 1.50 + 2e3 + 3f32\nnew c = 255u8\nnew d = -9223372036854775808 + -0x80i8 as i64 + -1\nnew s = \"tab\\tquote\\\" \\u{e9}\"\n";
+        assert_eq!(format(src), src);
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let src = "// leading\nnew x = 1 // trailing\n\n\n/// doc\nfunc f() {\n    /* inside */\n    x\n}\n\n{\n    // only a comment\n}\n// end\n";
+        let expected = "// leading\nnew x = 1 // trailing\n\n/// doc\nfunc f() {\n    /* inside */\n    x\n}\n\n{\n    // only a comment\n}\n// end\n";
+        assert_eq!(format(src), expected);
+    }
+
+    #[test]
+    fn splits_long_calls_and_parameter_lists() {
+        let src = "func long_function_name(first_parameter :: i64, second_parameter :: i64, third_parameter :: i64, fourth :: i64) {}\nlong_function_name(111111111111111111, 222222222222222222, 333333333333333333, 444444444444444444, 5)\n";
+        let formatted = format(src);
+        assert!(
+            formatted.lines().all(|l| l.len() <= MAX_WIDTH),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("    first_parameter :: i64,\n"),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("    111111111111111111,\n"),
+            "{formatted}"
+        );
+    }
+
+    #[test]
+    fn breaks_long_operator_chains() {
+        let src = "new total = 1000000000 + 2000000000 - 3000000000 + 4000000000 + 5000000000 + 6000000000 - 7000000000 + 8\nnew ok = total > 0 and total < 1000000000000 and total != 123456789 and total != 987654321 and total != 5\n";
+        let formatted = format(src);
+        let expected = "new total = 1000000000 + 2000000000 - 3000000000 + 4000000000 + 5000000000 + 6000000000 -\n    7000000000 + 8\nnew ok = total > 0 and total < 1000000000000 and total != 123456789 and total != 987654321 and\n    total != 5\n";
+        assert_eq!(formatted, expected);
+    }
+
+    #[test]
+    fn keeps_parentheses_and_not() {
+        let src = "new x = (1 + 2) * 3\nnew y = not x == 9\nnew z = -(2 ^ 2)\n";
+        assert_eq!(format(src), src);
+        let src = "new t = true\nnot t\n!t\nnot (t)\n";
+        assert_eq!(format(src), src);
+    }
+
+    #[test]
+    fn keeps_comments_in_front_of_their_token() {
+        let src = "func f(a :: i64, b :: i64) {}\nf(1, // one\n  2)\nf(1, 2 // two\n)\n";
+        let expected = "func f(a :: i64, b :: i64) {}\nf(\n    1, // one\n    2,\n)\nf(\n    1,\n    2, // two\n)\n";
+        assert_eq!(format(src), expected);
+
+        let src = "new x = /* inline */ 1\nnew y = (x /* a */ + 2) * /* b */ 3 as /* c */ i8\n";
+        assert_eq!(format(src), src);
+
+        let src = "func g(/* a */ a :: i64, b :: /* b */ i64) -> /* c */ i64 {\n    return a\n}\n";
+        assert_eq!(format(src), src);
+    }
+
+    #[test]
+    fn keeps_comments_between_branches() {
+        let src = "if true { 1 } // after if\nelse { 2 }\n";
+        let expected = "if true {\n    1\n} // after if\nelse {\n    2\n}\n";
+        assert_eq!(format(src), expected);
+
+        let src = "new x = 1\nif x == 1 { 1 } /* a */ elif x == 2 { 2 } else /* b */ { 3 }\n";
+        let expected = "new x = 1\nif x == 1 {\n    1\n} /* a */ elif x == 2 {\n    2\n} else /* b */ {\n    3\n}\n";
+        assert_eq!(format(src), expected);
+    }
+}
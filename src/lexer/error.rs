@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::{
+    diagnostics::diagnostic::Diagnostic,
+    lexer::{lexer::NUMBER_SUFFIXES, token::Span},
+};
+
+/// Problems found while turning source text into tokens. The lexer records these and keeps
+/// scanning, so a single run reports every bad character in the file.
+#[derive(Debug, Clone)]
+pub enum LexError {
+    /// A character that doesn't start any token
+    UnknownChar { ch: char, span: Span },
+
+    /// A string literal that reaches the end of the file without a closing `"`
+    UnterminatedString { span: Span },
+
+    /// A `/*` without a matching `*/`
+    UnterminatedComment { span: Span },
+
+    /// A `\` in a string followed by something that isn't a known escape
+    InvalidEscape { ch: char, span: Span },
+
+    /// A `\u{...}` escape that is badly formed or isn't a valid character
+    InvalidUnicodeEscape { value: String, span: Span },
+
+    /// A number literal that can't be read as a number, such as `1.2.3`, `reason` explains why
+    MalformedNumber {
+        value: String,
+        reason: &'static str,
+        span: Span,
+    },
+
+    /// A digit that is too big for the base of the number, like the `2` in `0b102`
+    InvalidDigit { ch: char, radix: u32, span: Span },
+
+    /// A number ending in letters that aren't a type suffix, like `10abc`
+    UnknownSuffix { suffix: String, span: Span },
+
+    /// An integer suffix on a float, or a float suffix on a number with a base prefix
+    MismatchedSuffix {
+        suffix: String,
+        literal: &'static str,
+        span: Span,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::UnknownChar { span, .. } => span,
+            Self::UnterminatedString { span } => span,
+            Self::UnterminatedComment { span } => span,
+            Self::InvalidEscape { span, .. } => span,
+            Self::InvalidUnicodeEscape { span, .. } => span,
+            Self::MalformedNumber { span, .. } => span,
+            Self::InvalidDigit { span, .. } => span,
+            Self::UnknownSuffix { span, .. } => span,
+            Self::MismatchedSuffix { span, .. } => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::UnknownChar { .. } => diag.with_label("not valid here"),
+            Self::UnterminatedString { .. } => diag
+                .with_label("string starts here")
+                .with_note("add a `\"` to close the string"),
+            Self::UnterminatedComment { .. } => diag
+                .with_label("comment starts here")
+                .with_note("block comments nest, so every `/*` needs its own `*/`"),
+            Self::InvalidEscape { .. } => diag.with_label("unknown escape").with_note(
+                "valid escapes are `\\\"`, `\\\\`, `\\n`, `\\t`, `\\r`, `\\0` and `\\u{...}`",
+            ),
+            Self::InvalidUnicodeEscape { .. } => diag.with_note(
+                "write the character's code point as 1 to 6 hex digits, like `\\u{1F600}`",
+            ),
+            Self::MalformedNumber { reason, .. } => diag.with_note(*reason),
+            Self::InvalidDigit { radix, .. } => {
+                diag.with_label(format!("not a base {radix} digit"))
+            }
+            Self::UnknownSuffix { .. } => diag.with_note(format!(
+                "valid suffixes are {}",
+                NUMBER_SUFFIXES.map(|s| format!("`{s}`")).join(", ")
+            )),
+            Self::MismatchedSuffix { .. } => diag.with_label("not allowed here"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownChar { ch, .. } => write!(f, "unknown character `{ch}`"),
+            Self::UnterminatedString { .. } => write!(f, "unterminated string literal"),
+            Self::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
+            Self::InvalidEscape { ch, .. } => write!(f, "unknown escape sequence `\\{ch}`"),
+            Self::InvalidUnicodeEscape { value, .. } => {
+                write!(f, "invalid unicode escape `{value}`")
+            }
+            Self::MalformedNumber { value, .. } => write!(f, "malformed number `{value}`"),
+            Self::InvalidDigit { ch, radix, .. } => {
+                write!(f, "invalid digit `{ch}` in a base {radix} number")
+            }
+            Self::UnknownSuffix { suffix, .. } => write!(f, "unknown number suffix `{suffix}`"),
+            Self::MismatchedSuffix {
+                suffix, literal, ..
+            } => write!(f, "a {literal} literal can't have the suffix `{suffix}`"),
+        }
+    }
+}
@@ -1,11 +1,20 @@
 use core::str;
 
-use crate::lexer::token::{self, Span, Token, TokenKind};
+use crate::{
+    lexer::{
+        error::LexError,
+        token::{Comment, CommentKind, Span, Token, TokenKind},
+    },
+    trace,
+};
 
 pub struct Lexer<'a> {
     pub src: &'a [u8],
     pub idx: usize,
     pub output: Vec<Token>,
+    pub errors: Vec<LexError>,
+    /// Comments waiting to be attached to the next token
+    pub trivia: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
@@ -15,10 +24,14 @@ impl<'a> Lexer<'a> {
             src: src.as_bytes(),
             idx: 0usize,
             output: Vec::new(),
+            errors: Vec::new(),
+            trivia: Vec::new(),
         }
     }
 
-    pub fn scan(&mut self) -> &Vec<Token> {
+    /// Scans the whole source. Bad input is recorded as a `LexError` and skipped so that scanning
+    /// always reaches the end of the file, the errors are returned if there were any.
+    pub fn scan(&mut self) -> Result<&Vec<Token>, &Vec<LexError>> {
         loop {
             // Push EOF + break when EOF condition reached
             if self.idx >= self.src.len() {
@@ -26,20 +39,24 @@ impl<'a> Lexer<'a> {
                 break;
             }
 
-            dbg!(self.src[self.idx] as char);
-
             // Match current slice
             match &self.src[self.idx..] {
-                // Ignore useless chars
-                [b' ', ..] | [b'\n', ..] | [b'\t', ..] | [b'\r', ..] => self.idx += 1,
+                // Ignore useless chars, newlines are kept since they end statements
+                [b' ', ..] | [b'\t', ..] | [b'\r', ..] => self.idx += 1,
+                [b'\n', ..] => self.push_token(TokenKind::Newline, self.idx, 1),
 
                 [b'+', b'=', ..] => self.push_token(TokenKind::PlusEqual, self.idx, 2),
                 [b'-', b'=', ..] => self.push_token(TokenKind::MinusEqual, self.idx, 2),
                 [b'-', b'>', ..] => self.push_token(TokenKind::RArrow, self.idx, 2),
-                [b'/', b'/', ..] => self.push_token(TokenKind::SlashSlash, self.idx, 2),
+                // Comments, `////` is a plain comment rather than a doc comment
+                [b'/', b'/', b'/', b'/', ..] => self.take_line_comment(CommentKind::Line),
+                [b'/', b'/', b'/', ..] => self.take_line_comment(CommentKind::Doc),
+                [b'/', b'/', ..] => self.take_line_comment(CommentKind::Line),
+                [b'/', b'*', ..] => self.take_block_comment(),
                 [b'<', b'-', ..] => self.push_token(TokenKind::LArrow, self.idx, 2),
                 [b':', b':', ..] => self.push_token(TokenKind::ColonColon, self.idx, 2),
                 [b':', b'=', ..] => self.push_token(TokenKind::ColonEqual, self.idx, 2),
+                [b'.', b'.', ..] => self.push_token(TokenKind::DotDot, self.idx, 2),
                 [b'<', b'=', ..] => self.push_token(TokenKind::LessEqual, self.idx, 2),
                 [b'>', b'=', ..] => self.push_token(TokenKind::MoreEqual, self.idx, 2),
                 [b'=', b'=', ..] => self.push_token(TokenKind::EqualEqual, self.idx, 2),
@@ -75,47 +92,171 @@ impl<'a> Lexer<'a> {
                     // Save current index for token span
                     let i0 = self.idx;
                     let id = self.take_ident();
-                    dbg!(&id.len());
 
                     // Push tokens based on keyword match result
                     if let Some(kind) = TokenKind::get_keyword(&id) {
-                        self.output.push(Token {
-                            kind,
-                            span: Span::from(i0, id.len()),
-                        });
+                        self.emit(kind, Span::from(i0, id.len()));
                     } else {
                         self.push_ident(i0, id.len(), id);
                     }
 
                     continue;
                 }
-                _ => {}
+                _ => self.skip_unknown(),
             }
         }
-        &self.output
+
+        trace!(
+            Lexer,
+            Debug,
+            "scanned {} tokens with {} errors",
+            self.output.len(),
+            self.errors.len()
+        );
+        if self.errors.is_empty() {
+            Ok(&self.output)
+        } else {
+            Err(&self.errors)
+        }
+    }
+
+    /// Records the character under the cursor as unknown and steps over all of its bytes
+    fn skip_unknown(&mut self) {
+        let (ch, len) = self.decode_char(self.idx);
+        self.errors.push(LexError::UnknownChar {
+            ch,
+            span: Span::from(self.idx, len),
+        });
+        self.idx += len;
     }
 
+    /// Decodes the UTF-8 character starting at `idx` and returns it with its width in bytes
+    fn decode_char(&self, idx: usize) -> (char, usize) {
+        let len = match self.src[idx] {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            _ => 4,
+        };
+        let end = (idx + len).min(self.src.len());
+        let ch = str::from_utf8(&self.src[idx..end])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        (ch, end - idx)
+    }
+
+    /// Scans a number literal: an optional `0x`, `0b` or `0o` prefix, digits with `_` separators,
+    /// a fraction and exponent for decimals, then an optional type suffix like `i64` or `f32`.
+    /// The whole literal is consumed before it's checked so one mistake is one error.
     fn take_number(&mut self) {
-        let mut buf = String::from(self.src[self.idx] as char);
         let i0 = self.idx;
-        self.idx += 1;
+        let radix = number_radix(&self.src[i0..]);
+        if radix != 10 {
+            self.idx += 2;
+        }
 
-        // Push every char until something that isn't num or _/. found
-        while self.idx < self.src.len()
-            && (self.src[self.idx].is_ascii_digit()
-                || self.src[self.idx] == b'_'
-                || self.src[self.idx] == b'.')
-        {
-            buf.push(self.src[self.idx] as char);
-            self.idx += 1;
+        loop {
+            match &self.src[self.idx..] {
+                // An exponent can be signed, so the sign belongs to the number here
+                [b'e' | b'E', b'+' | b'-', d, ..] if radix == 10 && d.is_ascii_digit() => {
+                    self.idx += 2
+                }
+                [c, ..] if c.is_ascii_alphanumeric() || *c == b'_' => self.idx += 1,
+                // A `.` only counts when a digit follows it so that ranges like `0..10` aren't
+                // read as one number
+                [b'.', d, ..] if radix == 10 && d.is_ascii_digit() => self.idx += 1,
+                _ => break,
+            }
+        }
+
+        if let Err(e) = self.check_number(i0, radix) {
+            self.errors.push(e);
+            return;
         }
 
         // Push token to output
-        let len = buf.len();
-        self.output.push(Token {
-            kind: TokenKind::Number { value: buf },
-            span: Span::from(i0, len),
-        });
+        let value = String::from_utf8_lossy(&self.src[i0..self.idx]).into_owned();
+        self.emit(TokenKind::Number { value }, Span::from(i0, self.idx - i0));
+    }
+
+    /// Checks the number literal in `i0..idx` is well formed, the parser relies on this when
+    /// converting it to a value
+    fn check_number(&self, i0: usize, radix: u32) -> Result<(), LexError> {
+        let text = str::from_utf8(&self.src[i0..self.idx]).unwrap_or_default();
+        let malformed = |reason| LexError::MalformedNumber {
+            value: text.to_string(),
+            reason,
+            span: Span::from(i0, text.len()),
+        };
+
+        let prefix = if radix == 10 { 0 } else { 2 };
+        let (digits, suffix) = split_number_suffix(&text[prefix..], radix);
+        let suffix_at = i0 + prefix + digits.len();
+
+        if radix == 10 {
+            let mut parts = digits.splitn(2, ['e', 'E']);
+            let mantissa = parts.next().unwrap_or_default();
+            if mantissa.matches('.').count() > 1 {
+                return Err(malformed("a number may contain at most one decimal point"));
+            }
+            if let Some(exponent) = parts.next() {
+                let exponent = exponent.trim_start_matches(['+', '-']);
+                if !exponent.starts_with(|c: char| c.is_ascii_digit())
+                    || !exponent.bytes().all(|b| b.is_ascii_digit() || b == b'_')
+                {
+                    return Err(malformed(
+                        "an exponent must be written as digits, like `1e3`",
+                    ));
+                }
+            }
+        } else {
+            if !digits.bytes().any(|b| b != b'_') {
+                return Err(malformed(
+                    "a number prefix needs at least one digit after it, like `0x1F`",
+                ));
+            }
+            let digit_at = i0 + prefix;
+            if let Some((at, ch)) = digits
+                .char_indices()
+                .find(|(_, c)| *c != '_' && !c.is_digit(radix))
+            {
+                return Err(LexError::InvalidDigit {
+                    ch,
+                    radix,
+                    span: Span::from(digit_at + at, 1),
+                });
+            }
+        }
+
+        if suffix.is_empty() {
+            return Ok(());
+        }
+
+        let span = Span::from(suffix_at, suffix.len());
+        if !NUMBER_SUFFIXES.contains(&suffix) {
+            return Err(LexError::UnknownSuffix {
+                suffix: suffix.to_string(),
+                span,
+            });
+        }
+
+        // Float suffixes only make sense on decimals and integer suffixes on whole numbers
+        let literal = match radix {
+            2 => "binary",
+            8 => "octal",
+            16 => "hexadecimal",
+            _ if digits.contains(['.', 'e', 'E']) => "float",
+            _ => return Ok(()),
+        };
+        if suffix.starts_with('f') != (literal == "float") {
+            return Err(LexError::MismatchedSuffix {
+                suffix: suffix.to_string(),
+                literal,
+                span,
+            });
+        }
+        Ok(())
     }
 
     fn take_literal(&mut self) {
@@ -125,18 +266,98 @@ impl<'a> Lexer<'a> {
 
         // Push every char until idx out of bounds or " found
         while self.idx < self.src.len() && self.src[self.idx] != b'"' {
-            buf.push(self.src[self.idx] as char);
-            self.idx += 1;
+            if self.src[self.idx] == b'\\' {
+                if let Some(ch) = self.take_escape() {
+                    buf.push(ch);
+                }
+                continue;
+            }
+            let (ch, len) = self.decode_char(self.idx);
+            buf.push(ch);
+            self.idx += len;
         }
 
-        // Push token to output
-        self.output.push(Token {
-            kind: TokenKind::Literal { value: buf },
-            span: Span::from(i0, self.idx),
-        });
+        // Reached EOF without finding the closing "
+        if self.idx >= self.src.len() {
+            self.errors.push(LexError::UnterminatedString {
+                span: Span::from(i0, self.idx - i0),
+            });
+            return;
+        }
 
         // Skip the enclosing "
         self.idx += 1;
+
+        // Push token to output
+        self.emit(
+            TokenKind::Literal { value: buf },
+            Span::from(i0, self.idx - i0),
+        );
+    }
+
+    /// Reads the escape sequence under the cursor, one of `\"`, `\\`, `\n`, `\t`, `\r`, `\0` or
+    /// `\u{...}` with one to six hex digits. Returns `None` and records an error if it's invalid.
+    fn take_escape(&mut self) -> Option<char> {
+        let i0 = self.idx;
+        self.idx += 1; // advance past the \
+
+        if self.idx >= self.src.len() {
+            return None; // reported as an unterminated string
+        }
+
+        let (ch, len) = self.decode_char(self.idx);
+        self.idx += len;
+        let escaped = match ch {
+            '"' => '"',
+            '\\' => '\\',
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            'u' => return self.take_unicode_escape(i0),
+            _ => {
+                self.errors.push(LexError::InvalidEscape {
+                    ch,
+                    span: Span::from(i0, self.idx - i0),
+                });
+                return None;
+            }
+        };
+        Some(escaped)
+    }
+
+    /// Reads the `{...}` part of a `\u{...}` escape, `i0` is where the `\` is
+    fn take_unicode_escape(&mut self, i0: usize) -> Option<char> {
+        let invalid = |lexer: &mut Self| {
+            let value = String::from_utf8_lossy(&lexer.src[i0..lexer.idx]).into_owned();
+            lexer.errors.push(LexError::InvalidUnicodeEscape {
+                value,
+                span: Span::from(i0, lexer.idx - i0),
+            });
+            None
+        };
+
+        if self.src.get(self.idx) != Some(&b'{') {
+            return invalid(self);
+        }
+        self.idx += 1;
+
+        let digits = self.idx;
+        while self.idx < self.src.len() && self.src[self.idx].is_ascii_hexdigit() {
+            self.idx += 1;
+        }
+        let hex = str::from_utf8(&self.src[digits..self.idx]).unwrap_or_default();
+
+        if self.src.get(self.idx) != Some(&b'}') {
+            return invalid(self);
+        }
+        self.idx += 1;
+
+        // Surrogates and anything past U+10FFFF aren't characters
+        match u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() <= 6) {
+            Some(code) => char::from_u32(code).or_else(|| invalid(self)),
+            None => invalid(self),
+        }
     }
 
     fn take_ident(&mut self) -> String {
@@ -153,20 +374,199 @@ impl<'a> Lexer<'a> {
         buf
     }
 
-    fn push_token(&mut self, kind: TokenKind, start: usize, len: usize) {
-        self.output.push(Token {
+    /// Reads a `//` or `///` comment up to the end of the line, the newline is left alone since it
+    /// still ends the statement
+    fn take_line_comment(&mut self, kind: CommentKind) {
+        let i0 = self.idx;
+        while self.idx < self.src.len() && self.src[self.idx] != b'\n' {
+            self.idx += 1;
+        }
+        self.push_comment(kind, i0);
+    }
+
+    /// Reads a `/* */` comment, block comments nest so `/* /* */ */` is one comment
+    fn take_block_comment(&mut self) {
+        let i0 = self.idx;
+        let mut depth = 0usize;
+
+        loop {
+            match &self.src[self.idx..] {
+                [b'/', b'*', ..] => {
+                    depth += 1;
+                    self.idx += 2;
+                }
+                [b'*', b'/', ..] => {
+                    depth -= 1;
+                    self.idx += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                [_, ..] => self.idx += 1,
+                [] => {
+                    self.errors.push(LexError::UnterminatedComment {
+                        span: Span::from(i0, 2),
+                    });
+                    return;
+                }
+            }
+        }
+        self.push_comment(CommentKind::Block, i0);
+    }
+
+    fn push_comment(&mut self, kind: CommentKind, start: usize) {
+        trace!(
+            Lexer,
+            Trace,
+            "{kind:?} comment at {}",
+            Span::from(start, self.idx - start)
+        );
+        self.trivia.push(Comment {
             kind,
-            span: Span::from(start, len),
+            text: String::from_utf8_lossy(&self.src[start..self.idx]).into_owned(),
+            span: Span::from(start, self.idx - start),
         });
+    }
+
+    /// Pushes a token to the output, taking the comments that came before it with it. Newlines
+    /// leave them for the next token so a doc comment ends up on what it documents.
+    fn emit(&mut self, kind: TokenKind, span: Span) {
+        trace!(
+            Lexer,
+            Trace,
+            "token {} {} at {span}",
+            self.output.len(),
+            kind.name()
+        );
+        let trivia = if kind == TokenKind::Newline {
+            Vec::new()
+        } else {
+            std::mem::take(&mut self.trivia)
+        };
+        self.output.push(Token { kind, span, trivia });
+    }
+
+    fn push_token(&mut self, kind: TokenKind, start: usize, len: usize) {
+        self.emit(kind, Span::from(start, len));
 
         // Advance index by length of token
         self.idx += len;
     }
 
     fn push_ident(&mut self, start: usize, len: usize, value: String) {
-        self.output.push(Token {
-            kind: TokenKind::Ident { value },
-            span: Span::from(start, len),
-        });
+        self.emit(TokenKind::Ident { value }, Span::from(start, len));
+    }
+}
+
+/// Type suffixes a number literal may end with, like `10i64` or `2.0f32`
+pub const NUMBER_SUFFIXES: [&str; 10] = [
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64",
+];
+
+/// The base of the number literal at the start of `src`, going by its prefix
+pub fn number_radix(src: &[u8]) -> u32 {
+    match src {
+        [b'0', b'x', ..] => 16,
+        [b'0', b'b', ..] => 2,
+        [b'0', b'o', ..] => 8,
+        _ => 10,
+    }
+}
+
+/// Splits a number literal with its prefix removed into its digits and type suffix. Suffixes
+/// start at the first letter that can't be a digit, `e` is an exponent in decimals and `a`..`f`
+/// are digits in hexadecimals.
+pub fn split_number_suffix(text: &str, radix: u32) -> (&str, &str) {
+    let at = text.find(|c: char| match radix {
+        16 => matches!(c, 'i' | 'u'),
+        10 => c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E'),
+        _ => c.is_ascii_alphabetic(),
+    });
+    text.split_at(at.unwrap_or(text.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(src: &str) -> Vec<LexError> {
+        let mut lexer = Lexer::new(src);
+        lexer.scan().expect_err("source has errors").clone()
+    }
+
+    /// Span of the first occurrence of `text` in `src`
+    fn span(src: &str, text: &str) -> Span {
+        Span::from(src.find(text).expect("text occurs"), text.len())
+    }
+
+    #[test]
+    fn scans_tokens_and_keeps_comments_as_trivia() {
+        let src = "new x = 10u8 // ten\n/// doc\nfunc";
+        let mut lexer = Lexer::new(src);
+        let tokens = lexer.scan().expect("source lexes");
+        let kinds: Vec<&str> = tokens.iter().map(|t| t.kind.name()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "New",
+                "Ident",
+                "Equal",
+                "Number",
+                "Newline",
+                "Newline",
+                "Func",
+                "EndOfFile"
+            ]
+        );
+        assert_eq!(tokens[3].kind.value(), Some("10u8"));
+        assert_eq!(tokens[3].span, span(src, "10u8"));
+
+        // Comments wait past newlines for the next token
+        let trivia: Vec<&str> = tokens[6].trivia.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(trivia, ["// ten", "/// doc"]);
+        assert_eq!(tokens[6].trivia[1].kind, CommentKind::Doc);
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_input() {
+        let src = "new s = \"a\\qb\" $ 0b102";
+        let found = errors(src);
+        assert!(matches!(&found[..], [
+            LexError::InvalidEscape { ch: 'q', span: escape },
+            LexError::UnknownChar { ch: '$', span: dollar },
+            LexError::InvalidDigit { ch: '2', radix: 2, span: digit },
+        ] if *escape == span(src, "\\q") && *dollar == span(src, "$") && *digit == span(src, "2")));
+
+        let src = "1\n\"never closed";
+        let found = errors(src);
+        assert!(matches!(&found[..], [LexError::UnterminatedString { span: s }] if s.start() == 2));
+
+        let src = "1 /* outer /* inner */";
+        let found = errors(src);
+        assert!(
+            matches!(&found[..], [LexError::UnterminatedComment { span: s }] if *s == span(src, "/*"))
+        );
+    }
+
+    #[test]
+    fn checks_number_suffixes() {
+        let mut lexer = Lexer::new("255u8 1_000i64 2.5f32 0xffu16 3e2 0x1f32");
+        let tokens = lexer.scan().expect("source lexes");
+        let values: Vec<&str> = tokens.iter().filter_map(|t| t.kind.value()).collect();
+        assert_eq!(
+            values,
+            ["255u8", "1_000i64", "2.5f32", "0xffu16", "3e2", "0x1f32"]
+        );
+
+        let src = "10abc 1.5u8 0x10f64 1.2.3";
+        let found = errors(src);
+        assert!(
+            matches!(&found[..], [
+            LexError::UnknownSuffix { suffix: abc, span: abc_span },
+            LexError::MismatchedSuffix { suffix: u8_suffix, .. },
+            LexError::MalformedNumber { .. },
+        ] if abc == "abc" && *abc_span == span(src, "abc") && u8_suffix == "u8"),
+            "{found:?}"
+        );
     }
 }
@@ -1,2 +1,5 @@
+pub mod error;
+#[allow(clippy::module_inception)]
+pub mod lexer;
+pub mod source;
 pub mod token;
-pub mod lexer;
\ No newline at end of file
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::lexer::token::Span;
+
+/// A human readable position in a source file, both fields start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Owns the text of a source file and knows where each line begins, so byte offsets from a
+/// `Span` can be turned into line/column positions without rescanning the file every time.
+pub struct SourceFile {
+    name: String,
+    src: String,
+    /// Byte offset of the first byte of every line
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, src: String) -> Self {
+        let mut line_starts = vec![0usize];
+        line_starts.extend(
+            src.bytes()
+                .enumerate()
+                .filter(|(_, b)| *b == b'\n')
+                .map(|(i, _)| i + 1),
+        );
+
+        Self {
+            name: name.to_string(),
+            src,
+            line_starts,
+        }
+    }
+
+    /// Adds `text` to the end of the file, only `text` is scanned for the lines it starts
+    pub fn push_str(&mut self, text: &str) {
+        let offset = self.src.len();
+        self.line_starts.extend(
+            text.bytes()
+                .enumerate()
+                .filter(|(_, b)| *b == b'\n')
+                .map(|(i, _)| offset + i + 1),
+        );
+        self.src.push_str(text);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// Returns the text of a line without its line ending, `line` starts at 1
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.src.len());
+        self.src[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// Converts a byte offset to a position. Columns count characters rather than bytes, so
+    /// multi-byte UTF-8 characters take up a single column, and an offset inside of a character
+    /// resolves to that character. Offsets past the end of the file clamp to the end.
+    pub fn position(&self, offset: usize) -> Position {
+        let (line, start, offset) = self.locate(offset);
+        Position {
+            line: line + 1,
+            column: self.src[start..offset].chars().count() + 1,
+        }
+    }
+
+    /// Like `position` but columns count UTF-16 code units, which is how editors speaking the
+    /// language server protocol count them
+    pub fn utf16_position(&self, offset: usize) -> Position {
+        let (line, start, offset) = self.locate(offset);
+        Position {
+            line: line + 1,
+            column: self.src[start..offset].encode_utf16().count() + 1,
+        }
+    }
+
+    /// Converts a position with a UTF-16 column back to a byte offset. Columns past the end of
+    /// the line clamp to the end of the line, and lines past the end of the file to the end of
+    /// the file.
+    pub fn utf16_offset(&self, position: Position) -> usize {
+        let Some(&start) = position
+            .line
+            .checked_sub(1)
+            .and_then(|line| self.line_starts.get(line))
+        else {
+            return self.src.len();
+        };
+
+        let text = self.line_text(position.line);
+        let mut column = 1usize;
+        for (i, c) in text.char_indices() {
+            if column >= position.column {
+                return start + i;
+            }
+            column += c.len_utf16();
+        }
+        start + text.len()
+    }
+
+    /// Clamps an offset to the file and backs it up to the start of the character it lands in,
+    /// returning it along with the zero based line it is on and where that line starts
+    fn locate(&self, offset: usize) -> (usize, usize, usize) {
+        let mut offset = offset.min(self.src.len());
+        while !self.src.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        (line, self.line_starts[line], offset)
+    }
+
+    /// Converts a span to the positions of its first and last characters
+    pub fn span_position(&self, span: &Span) -> (Position, Position) {
+        (self.position(span.start()), self.position(span.end()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appending_finds_the_same_lines() {
+        let src = "a\nbé\n\nc";
+        let mut file = SourceFile::new("<test>", String::new());
+        for part in ["a\nb", "é\n", "\nc"] {
+            file.push_str(part);
+        }
+        let whole = SourceFile::new("<test>", src.to_string());
+        assert_eq!(file.src(), src);
+        for offset in 0..=src.len() {
+            assert_eq!(file.position(offset), whole.position(offset));
+        }
+        assert_eq!(file.line_text(2), "bé");
+        assert_eq!(file.position(src.len()), Position { line: 4, column: 2 });
+    }
+}
@@ -1,6 +1,6 @@
-use std::ops::Range;
+use std::{fmt, ops::Range};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Span {
     range: Range<usize>,
 }
@@ -14,15 +14,78 @@ impl Span {
             },
         }
     }
+
+    /// Byte offset of the first byte in the span
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    /// Byte offset of the last byte in the span, this is inclusive
+    pub fn end(&self) -> usize {
+        self.range.end
+    }
+
+    /// Returns a span from the start of this one to the end of `other`
+    pub fn to(&self, other: &Span) -> Span {
+        Self {
+            range: Range {
+                start: self.range.start,
+                end: other.range.end,
+            },
+        }
+    }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.range.start, self.range.end)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
+    /// Comments between the previous token and this one
+    pub trivia: Vec<Comment>,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+/// A comment, kept as trivia on the token after it rather than thrown away
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// The comment as written, including `//` or `/* */`
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentKind {
+    /// `// ...` up to the end of the line, `////` is also a line comment
+    Line,
+    /// `/* ... */`, which may contain other block comments
+    Block,
+    /// `/// ...` documenting whatever comes after it
+    Doc,
+}
+
+impl Comment {
+    /// The text of a doc comment without the `///` and the space after it
+    pub fn doc_text(&self) -> Option<&str> {
+        let text = self.text.strip_prefix("///")?;
+        (self.kind == CommentKind::Doc).then(|| text.strip_prefix(' ').unwrap_or(text))
+    }
+}
+
+impl Token {
+    /// Joins the doc comments in front of this token into one string, one line per comment
+    pub fn doc(&self) -> Option<String> {
+        let lines: Vec<&str> = self.trivia.iter().filter_map(Comment::doc_text).collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub enum TokenKind {
     // Grouping tokens
     LPar,
@@ -39,7 +102,6 @@ pub enum TokenKind {
     MinusEqual,
     Star,
     Slash,
-    SlashSlash,
     Caret,
     Modulo,
 
@@ -55,6 +117,7 @@ pub enum TokenKind {
     Semicolon,
     Comma,
     Dot,
+    DotDot,
 
     // Comparison tokens
     More,
@@ -75,6 +138,16 @@ pub enum TokenKind {
     New,
     Mut,
     Func,
+    Return,
+    In,
+    Break,
+    Continue,
+    True,
+    False,
+    And,
+    Or,
+    Not,
+    As,
 
     // Literal tokens
     Literal { value: String },
@@ -82,13 +155,14 @@ pub enum TokenKind {
     Ident { value: String },
 
     // Other tokens
+    Newline,
     EndOfFile,
 }
 
 impl TokenKind {
     /// Takes a string as input and returns and option type containing the TokenKind that string pertains to.
-    pub fn get_keyword(src: &String) -> Option<TokenKind> {
-        match src.as_str() {
+    pub fn get_keyword(src: &str) -> Option<TokenKind> {
+        match src {
             "if" => Some(Self::If),
             "else" => Some(Self::Else),
             "elif" => Some(Self::Elif),
@@ -97,35 +171,182 @@ impl TokenKind {
             "new" => Some(Self::New),
             "mut" => Some(Self::Mut),
             "func" => Some(Self::Func),
+            "return" => Some(Self::Return),
+            "in" => Some(Self::In),
+            "break" => Some(Self::Break),
+            "continue" => Some(Self::Continue),
+            "true" => Some(Self::True),
+            "false" => Some(Self::False),
+            "and" => Some(Self::And),
+            "or" => Some(Self::Or),
+            "not" => Some(Self::Not),
+            "as" => Some(Self::As),
+            _ => None,
+        }
+    }
+
+    /// The text carried by literal and identifier tokens
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Self::Literal { value } | Self::Number { value } | Self::Ident { value } => Some(value),
             _ => None,
         }
     }
 
-    /// Returns `true` or `false` based on if the given variant is a leaf node in the AST
-    pub fn is_leaf_node(&self) -> bool {
+    /// Name of the variant, used when tokens are written out for other programs to read
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Literal { value: _ } => true,
-            Self::Number { value: _ } => true,
-            Self::Ident { value: _ } => true,
-            _ => false,
+            Self::LPar => "LPar",
+            Self::RPar => "RPar",
+            Self::LBrac => "LBrac",
+            Self::RBrac => "RBrac",
+            Self::LCurl => "LCurl",
+            Self::RCurl => "RCurl",
+            Self::Plus => "Plus",
+            Self::PlusEqual => "PlusEqual",
+            Self::Minus => "Minus",
+            Self::MinusEqual => "MinusEqual",
+            Self::Star => "Star",
+            Self::Slash => "Slash",
+            Self::Caret => "Caret",
+            Self::Modulo => "Modulo",
+            Self::LArrow => "LArrow",
+            Self::RArrow => "RArrow",
+            Self::Hash => "Hash",
+            Self::At => "At",
+            Self::Ampersand => "Ampersand",
+            Self::Colon => "Colon",
+            Self::ColonColon => "ColonColon",
+            Self::ColonEqual => "ColonEqual",
+            Self::Semicolon => "Semicolon",
+            Self::Comma => "Comma",
+            Self::Dot => "Dot",
+            Self::DotDot => "DotDot",
+            Self::More => "More",
+            Self::MoreEqual => "MoreEqual",
+            Self::Less => "Less",
+            Self::LessEqual => "LessEqual",
+            Self::Equal => "Equal",
+            Self::EqualEqual => "EqualEqual",
+            Self::Bang => "Bang",
+            Self::BangEqual => "BangEqual",
+            Self::If => "If",
+            Self::Else => "Else",
+            Self::Elif => "Elif",
+            Self::For => "For",
+            Self::While => "While",
+            Self::New => "New",
+            Self::Mut => "Mut",
+            Self::Func => "Func",
+            Self::Return => "Return",
+            Self::In => "In",
+            Self::Break => "Break",
+            Self::Continue => "Continue",
+            Self::True => "True",
+            Self::False => "False",
+            Self::And => "And",
+            Self::Or => "Or",
+            Self::Not => "Not",
+            Self::As => "As",
+            Self::Literal { .. } => "Literal",
+            Self::Number { .. } => "Number",
+            Self::Ident { .. } => "Ident",
+            Self::Newline => "Newline",
+            Self::EndOfFile => "EndOfFile",
         }
     }
 
-    /// Determines if the given variant is a binary operator, and if so returns it's precedence/index
+    /// Determines if the given variant is a binary operator, and if so returns it's precedence/index.
+    /// A lower precedence binds tighter, so `^` (0) is applied before `as` casts (1), `*` (2) and
+    /// `+` (3), then comparisons (4), equality (5), `and` (6) and finally `or` (7)
     pub fn binary_operator(&self) -> (u8, u8) {
         match self {
-            Self::Plus => ('+' as u8, 2),
-            Self::Minus => ('-' as u8, 2),
-            Self::Star => ('*' as u8, 1),
-            Self::Slash => ('/' as u8, 1),
-            Self::Modulo => ('%' as u8, 1),
-            Self::Caret => ('^' as u8, 0),
+            Self::Plus => (b'+', 3),
+            Self::Minus => (b'-', 3),
+            Self::Star => (b'*', 2),
+            Self::Slash => (b'/', 2),
+            Self::Modulo => (b'%', 2),
+            Self::Caret => (b'^', 0),
+            Self::Less => (b'<', 4),
+            Self::LessEqual => (b'L', 4),
+            Self::More => (b'>', 4),
+            Self::MoreEqual => (b'G', 4),
+            Self::EqualEqual => (b'=', 5),
+            Self::BangEqual => (b'!', 5),
+            Self::And => (b'&', 6),
+            Self::Or => (b'|', 7),
             _ => (0u8, 0u8),
         }
     }
-}
-
-
-
 
+    /// Returns `true` if chains of this operator group from the right, i.e. `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`
+    pub fn is_right_assoc(&self) -> bool {
+        matches!(self, Self::Caret)
+    }
+}
 
+/// Writes the token the way it would appear in source, used when reporting errors
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lexeme = match self {
+            Self::LPar => "(",
+            Self::RPar => ")",
+            Self::LBrac => "[",
+            Self::RBrac => "]",
+            Self::LCurl => "{",
+            Self::RCurl => "}",
+            Self::Plus => "+",
+            Self::PlusEqual => "+=",
+            Self::Minus => "-",
+            Self::MinusEqual => "-=",
+            Self::Star => "*",
+            Self::Slash => "/",
+            Self::Caret => "^",
+            Self::Modulo => "%",
+            Self::LArrow => "<-",
+            Self::RArrow => "->",
+            Self::Hash => "#",
+            Self::At => "@",
+            Self::Ampersand => "&",
+            Self::Colon => ":",
+            Self::ColonColon => "::",
+            Self::ColonEqual => ":=",
+            Self::Semicolon => ";",
+            Self::Comma => ",",
+            Self::Dot => ".",
+            Self::DotDot => "..",
+            Self::More => ">",
+            Self::MoreEqual => ">=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Equal => "=",
+            Self::EqualEqual => "==",
+            Self::Bang => "!",
+            Self::BangEqual => "!=",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::Elif => "elif",
+            Self::For => "for",
+            Self::While => "while",
+            Self::New => "new",
+            Self::Mut => "mut",
+            Self::Func => "func",
+            Self::Return => "return",
+            Self::In => "in",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::True => "true",
+            Self::False => "false",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Not => "not",
+            Self::As => "as",
+            Self::Literal { value } => return write!(f, "string \"{value}\""),
+            Self::Number { value } => return write!(f, "number `{value}`"),
+            Self::Ident { value } => return write!(f, "identifier `{value}`"),
+            Self::Newline => return write!(f, "newline"),
+            Self::EndOfFile => return write!(f, "end of file"),
+        };
+        write!(f, "`{lexeme}`")
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        node::{FunctionExpr, Node},
+        parser::Parser,
+    },
+    check::checker::Checker,
+    diagnostics::diagnostic::{Diagnostic, Severity},
+    lexer::{
+        error::LexError,
+        lexer::Lexer,
+        source::SourceFile,
+        token::{Span, Token, TokenKind},
+    },
+    resolve::resolver::{Bindings, Resolver},
+    typeck::infer::{TypeChecker, Types},
+};
+
+/// What a declared name is, used to colour its uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+/// An open file, named by its URI, along with everything the compiler found out about it. Each
+/// stage only runs if the ones before it succeeded, the same as on the command line, so a file
+/// that doesn't parse has no AST.
+pub struct Document {
+    pub file: SourceFile,
+    pub tokens: Vec<Token>,
+    pub ast: Vec<Node>,
+    pub bindings: Bindings,
+    pub types: Types,
+    pub declarations: HashMap<Span, DeclKind>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(uri: &str, text: String) -> Self {
+        let mut doc = Self {
+            file: SourceFile::new(uri, text),
+            tokens: Vec::new(),
+            ast: Vec::new(),
+            bindings: HashMap::new(),
+            types: HashMap::new(),
+            declarations: HashMap::new(),
+            diagnostics: Vec::new(),
+        };
+        doc.analyse();
+        doc
+    }
+
+    fn analyse(&mut self) {
+        let mut lexer = Lexer::new(self.file.src());
+        match lexer.scan() {
+            Ok(tokens) => self.tokens = tokens.clone(),
+            Err(errors) => {
+                self.diagnostics = errors.iter().map(LexError::diagnostic).collect();
+                return;
+            }
+        }
+        match Parser::new(&self.tokens).parse() {
+            Ok(ast) => self.ast = ast,
+            Err(errors) => {
+                self.diagnostics = errors.iter().map(|e| e.diagnostic()).collect();
+                return;
+            }
+        }
+        for node in &self.ast {
+            collect_declarations(node, &mut self.declarations);
+        }
+
+        let (bindings, errors) = Resolver::new().resolve(&self.ast);
+        self.bindings = bindings;
+        self.diagnostics = errors.iter().map(|e| e.diagnostic()).collect();
+        if errors.iter().any(|e| e.severity() == Severity::Error) {
+            return;
+        }
+        if let Err(errors) = Checker::new().check(&self.ast, &self.bindings) {
+            self.diagnostics
+                .extend(errors.iter().map(|e| e.diagnostic()));
+            return;
+        }
+        match TypeChecker::new().check(&self.ast, &self.bindings) {
+            Ok(types) => self.types = types.clone(),
+            Err(errors) => self
+                .diagnostics
+                .extend(errors.iter().map(|e| e.diagnostic())),
+        }
+    }
+
+    /// The identifier token containing the byte at `offset`, or ending right before it so that
+    /// a cursor placed after a name still finds it
+    pub fn ident_at(&self, offset: usize) -> Option<&Token> {
+        let is_ident = |t: &&Token| matches!(t.kind, TokenKind::Ident { .. });
+        let within = |t: &&Token| t.span.start() <= offset && offset <= t.span.end() + 1;
+        self.tokens.iter().filter(is_ident).find(within)
+    }
+
+    /// Where the name at `span` was declared, declarations refer to themselves
+    pub fn declaration(&self, span: &Span) -> Option<&Span> {
+        self.bindings
+            .get(span)
+            .or_else(|| self.declarations.get_key_value(span).map(|(k, _)| k))
+    }
+
+    /// Every function in the file, including ones declared inside of other functions, in the
+    /// order they are written
+    pub fn functions(&self) -> Vec<&Rc<FunctionExpr>> {
+        let mut functions = Vec::new();
+        for node in &self.ast {
+            collect_functions(node, &mut functions);
+        }
+        functions
+    }
+
+    /// Span from a function's `func` keyword to the `}` ending its body
+    pub fn function_span(&self, func: &FunctionExpr) -> Span {
+        let name = &func.signature.name.span;
+        let Some(i) = self.tokens.iter().position(|t| t.span == *name) else {
+            return name.clone();
+        };
+        let start = match i.checked_sub(1).map(|i| &self.tokens[i]) {
+            Some(t) if t.kind == TokenKind::Func => &t.span,
+            _ => name,
+        };
+
+        let mut depth = 0usize;
+        for token in &self.tokens[i..] {
+            match token.kind {
+                TokenKind::LCurl => depth += 1,
+                TokenKind::RCurl if depth == 1 => return start.to(&token.span),
+                TokenKind::RCurl => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        start.to(name)
+    }
+}
+
+/// Written the way the function is declared, e.g. `func add(a :: i64, b :: i64) -> i64`
+pub fn signature(func: &FunctionExpr) -> String {
+    let sig = &func.signature;
+    let params: Vec<String> = sig
+        .params
+        .iter()
+        .map(|p| {
+            let prefix = if p.mutable { "mut " } else { "" };
+            format!("{prefix}{} :: {}", p.name.name, p.annotation.name)
+        })
+        .collect();
+    let mut out = format!("func {}({})", sig.name.name, params.join(", "));
+    if let Some(ty) = &sig.returns {
+        out.push_str(&format!(" -> {}", ty.name));
+    }
+    out
+}
+
+fn collect_declarations(node: &Node, out: &mut HashMap<Span, DeclKind>) {
+    match node {
+        Node::Let(l) => {
+            out.insert(l.name.span.clone(), DeclKind::Variable);
+        }
+        Node::Function(func) => {
+            out.insert(func.signature.name.span.clone(), DeclKind::Function);
+            for param in &func.signature.params {
+                out.insert(param.name.span.clone(), DeclKind::Parameter);
+            }
+        }
+        Node::For(stmt) => {
+            out.insert(stmt.var.span.clone(), DeclKind::Variable);
+        }
+        _ => {}
+    }
+    for body in bodies(node) {
+        for node in body {
+            collect_declarations(node, out);
+        }
+    }
+}
+
+fn collect_functions<'a>(node: &'a Node, out: &mut Vec<&'a Rc<FunctionExpr>>) {
+    if let Node::Function(func) = node {
+        out.push(func);
+    }
+    for body in bodies(node) {
+        for node in body {
+            collect_functions(node, out);
+        }
+    }
+}
+
+/// The blocks of statements directly inside of a statement
+pub fn bodies(node: &Node) -> Vec<&[Node]> {
+    match node {
+        Node::Function(func) => vec![&func.body],
+        Node::Block(body) => vec![body],
+        Node::If(stmt) => stmt
+            .branches
+            .iter()
+            .map(|b| b.body.as_slice())
+            .chain(stmt.otherwise.as_deref())
+            .collect(),
+        Node::While(stmt) => vec![&stmt.body],
+        Node::For(stmt) => vec![&stmt.body],
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt;
+
+/// A JSON value read from the client. Messages sent back are built as `serialize::tree::Tree`s
+/// instead, since every field name in them is known ahead of time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// Fields in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser {
+            src: text.as_bytes(),
+            pos: 0usize,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.src.len() {
+            return Err(parser.error("unexpected data after the value"));
+        }
+        Ok(value)
+    }
+
+    /// Follows a path of object fields, e.g. `["textDocument", "uri"]`
+    pub fn get(&self, path: &[&str]) -> Option<&Json> {
+        path.iter().try_fold(self, |value, key| match value {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Text that isn't valid JSON, `offset` is the byte the problem was found at
+#[derive(Debug, Clone)]
+pub struct JsonError {
+    pub message: &'static str,
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+struct JsonParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) if self.eat_word("true") => Ok(Json::Bool(true)),
+            Some(_) if self.eat_word("false") => Ok(Json::Bool(false)),
+            Some(_) if self.eat_word("null") => Ok(Json::Null),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a field name"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return Err(self.error("expected `:` after a field name"));
+            }
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(b) = self.next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0u8; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                b => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("string is not valid UTF-8"))
+    }
+
+    /// Reads the digits of a `\u` escape, joining a surrogate pair into one character
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        if !(self.eat(b'\\') && self.eat(b'u')) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("expected 4 hex digits"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.src[start..self.pos])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .map(Json::Number)
+            .ok_or(JsonError {
+                message: "invalid number",
+                offset: start,
+            })
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let matched = self.src[self.pos..].starts_with(word.as_bytes());
+        if matched {
+            self.pos += word.len();
+        }
+        matched
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let matched = self.peek() == Some(b);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek();
+        self.pos += 1;
+        b
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError {
+            message,
+            offset: self.pos,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Result<String, &'static str> {
+        match Json::parse(text) {
+            Ok(Json::Str(s)) => Ok(s),
+            Ok(value) => panic!("expected a string, got {value:?}"),
+            Err(e) => Err(e.message),
+        }
+    }
+
+    #[test]
+    fn reads_nested_values() {
+        let json = Json::parse(r#" {"a": {"b": [1, -2.5e1, true, null]}, "c": "d"} "#).unwrap();
+        let items = json.get(&["a", "b"]).and_then(Json::as_array).unwrap();
+        assert_eq!(items[0].as_usize(), Some(1));
+        assert_eq!(items[1], Json::Number(-25.0));
+        assert_eq!(items[2..], [Json::Bool(true), Json::Null]);
+        assert_eq!(json.get(&["c"]).and_then(Json::as_str), Some("d"));
+        assert_eq!(json.get(&["a", "missing"]), None);
+        assert_eq!(json.get(&["c", "d"]), None);
+    }
+
+    #[test]
+    fn reads_escapes() {
+        assert_eq!(
+            string(r#""a\"b\\c\/d\n\t""#),
+            Ok("a\"b\\c/d\n\t".to_string())
+        );
+        assert_eq!(string(r#""\u00e9\u4e2d""#), Ok("é中".to_string()));
+        assert_eq!(string("\"é 😀\""), Ok("é 😀".to_string()));
+        assert_eq!(string(r#""\q""#), Err("invalid escape"));
+        assert_eq!(string(r#""\u12""#), Err("expected 4 hex digits"));
+        assert_eq!(string(r#""abc"#), Err("unterminated string"));
+    }
+
+    #[test]
+    fn joins_surrogate_pairs() {
+        assert_eq!(string(r#""\ud83d\ude00""#), Ok("😀".to_string()));
+        assert_eq!(string(r#""\ud83d""#), Err("unpaired surrogate"));
+        assert_eq!(string(r#""\ud83dx""#), Err("unpaired surrogate"));
+        assert_eq!(string(r#""\ud83d\u0041""#), Err("unpaired surrogate"));
+        assert_eq!(string(r#""\ude00""#), Err("invalid unicode escape"));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        let error = |text| Json::parse(text).unwrap_err().message;
+        assert_eq!(error(r#"{"a" 1}"#), "expected `:` after a field name");
+        assert_eq!(error("[1 2]"), "expected `,` or `]`");
+        assert_eq!(error("{1: 2}"), "expected a field name");
+        assert_eq!(error("nul"), "expected a value");
+        assert_eq!(error("1 2"), "unexpected data after the value");
+        assert_eq!(error(""), "unexpected end of input");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod document;
+pub mod json;
+pub mod server;
+pub mod transport;
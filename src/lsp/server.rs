@@ -0,0 +1,726 @@
+use std::{collections::HashMap, io};
+
+use crate::{
+    ast::node::Node,
+    diagnostics::diagnostic::{Diagnostic, Severity},
+    lexer::{
+        source::Position,
+        token::{CommentKind, Span, TokenKind},
+    },
+    serialize::{json::to_json, tree::Tree},
+};
+
+use super::{
+    document::{bodies, signature, DeclKind, Document},
+    json::Json,
+    transport::{read_message, write_message},
+};
+
+/// Kinds of semantic token, a token's kind is its index in this list
+const TOKEN_TYPES: [&str; 9] = [
+    "keyword",
+    "function",
+    "parameter",
+    "variable",
+    "type",
+    "number",
+    "string",
+    "operator",
+    "comment",
+];
+
+/// Flags a semantic token can have, bit `i` is set for the `i`th one
+const TOKEN_MODIFIERS: [&str; 2] = ["declaration", "documentation"];
+const DECLARATION: usize = 1 << 0;
+const DOCUMENTATION: usize = 1 << 1;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i128 = -32700;
+const INVALID_REQUEST: i128 = -32600;
+const METHOD_NOT_FOUND: i128 = -32601;
+const INVALID_PARAMS: i128 = -32602;
+const SERVER_NOT_INITIALIZED: i128 = -32002;
+
+/// `SymbolKind.Function` in the protocol
+const SYMBOL_FUNCTION: i128 = 12;
+
+type RequestResult = Result<Tree, (i128, String)>;
+
+/// A language server talking to one client over stdin and stdout. Files are analysed whenever
+/// they change, and diagnostics are published when they are opened or saved.
+pub struct Server {
+    /// Open files by URI
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+        }
+    }
+
+    /// Handles messages until the client sends `exit` or closes stdin. Exiting without asking
+    /// the server to shut down first is an error.
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        let mut output = io::stdout();
+        while let Some(body) = read_message(&mut input)? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    let reply = error_response(Tree::Null, PARSE_ERROR, e.to_string());
+                    write_message(&mut output, &to_json(&reply))?;
+                    continue;
+                }
+            };
+            if message.get(&["method"]).and_then(Json::as_str) == Some("exit") {
+                if !self.shutdown {
+                    return Err(io::Error::other("exited without being shut down"));
+                }
+                return Ok(());
+            }
+            for reply in self.handle(&message) {
+                write_message(&mut output, &to_json(&reply))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles one message and returns the messages to send back
+    fn handle(&mut self, message: &Json) -> Vec<Tree> {
+        let method = message.get(&["method"]).and_then(Json::as_str);
+        let params = message.get(&["params"]).unwrap_or(&Json::Null);
+        let id = match message.get(&["id"]) {
+            Some(Json::Number(n)) => Some(Tree::Int(*n as i128)),
+            Some(Json::Str(s)) => Some(Tree::Str(s.clone())),
+            _ => None,
+        };
+
+        match (method, id) {
+            (Some(method), Some(id)) => {
+                let result = match method {
+                    "initialize" => Ok(self.initialize()),
+                    _ if !self.initialized => Err((
+                        SERVER_NOT_INITIALIZED,
+                        "initialize was not sent".to_string(),
+                    )),
+                    "shutdown" => {
+                        self.shutdown = true;
+                        Ok(Tree::Null)
+                    }
+                    "textDocument/definition" => self.definition(params),
+                    "textDocument/hover" => self.hover(params),
+                    "textDocument/documentSymbol" => self.document_symbols(params),
+                    "textDocument/semanticTokens/full" => self.semantic_tokens(params),
+                    _ => Err((METHOD_NOT_FOUND, format!("`{method}` is not supported"))),
+                };
+                vec![match result {
+                    Ok(result) => response(id, result),
+                    Err((code, message)) => error_response(id, code, message),
+                }]
+            }
+            (Some(method), None) => self.notification(method, params),
+            // Replies to requests the server sent, which it never does
+            (None, Some(_)) => Vec::new(),
+            (None, None) => vec![error_response(
+                Tree::Null,
+                INVALID_REQUEST,
+                "message has no method".to_string(),
+            )],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Tree> {
+        let uri = params.get(&["textDocument", "uri"]).and_then(Json::as_str);
+        let Some(uri) = uri else {
+            return Vec::new();
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.get(&["textDocument", "text"]).and_then(Json::as_str);
+                let document = Document::new(uri, text.unwrap_or_default().to_string());
+                let diagnostics = self.publish(uri, &document);
+                self.documents.insert(uri.to_string(), document);
+                vec![diagnostics]
+            }
+            // Only full syncing is offered, so the last change holds the whole text
+            "textDocument/didChange" => {
+                let changes = params.get(&["contentChanges"]).and_then(Json::as_array);
+                let text = changes
+                    .and_then(<[Json]>::last)
+                    .and_then(|c| c.get(&["text"]))
+                    .and_then(Json::as_str);
+                if let Some(text) = text {
+                    self.documents
+                        .insert(uri.to_string(), Document::new(uri, text.to_string()));
+                }
+                Vec::new()
+            }
+            "textDocument/didSave" => match self.documents.get(uri) {
+                Some(document) => vec![self.publish(uri, document)],
+                None => Vec::new(),
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    Tree::Object(vec![
+                        ("uri", Tree::Str(uri.to_string())),
+                        ("diagnostics", Tree::List(Vec::new())),
+                    ]),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self) -> Tree {
+        self.initialized = true;
+        let names = |names: &[&str]| Tree::List(names.iter().map(|n| str(n)).collect());
+        let capabilities = Tree::Object(vec![
+            (
+                "textDocumentSync",
+                Tree::Object(vec![
+                    ("openClose", Tree::Bool(true)),
+                    // Full, the client sends the whole file on every change
+                    ("change", Tree::Int(1)),
+                    (
+                        "save",
+                        Tree::Object(vec![("includeText", Tree::Bool(false))]),
+                    ),
+                ]),
+            ),
+            ("definitionProvider", Tree::Bool(true)),
+            ("hoverProvider", Tree::Bool(true)),
+            ("documentSymbolProvider", Tree::Bool(true)),
+            (
+                "semanticTokensProvider",
+                Tree::Object(vec![
+                    (
+                        "legend",
+                        Tree::Object(vec![
+                            ("tokenTypes", names(&TOKEN_TYPES)),
+                            ("tokenModifiers", names(&TOKEN_MODIFIERS)),
+                        ]),
+                    ),
+                    ("full", Tree::Bool(true)),
+                ]),
+            ),
+        ]);
+
+        Tree::Object(vec![
+            ("capabilities", capabilities),
+            (
+                "serverInfo",
+                Tree::Object(vec![
+                    ("name", str("starkey")),
+                    ("version", str(env!("CARGO_PKG_VERSION"))),
+                ]),
+            ),
+        ])
+    }
+
+    fn publish(&self, uri: &str, document: &Document) -> Tree {
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|diag| diagnostic(uri, document, diag))
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            Tree::Object(vec![
+                ("uri", Tree::Str(uri.to_string())),
+                ("diagnostics", Tree::List(diagnostics)),
+            ]),
+        )
+    }
+
+    fn definition(&self, params: &Json) -> RequestResult {
+        let (uri, document, offset) = self.locate(params)?;
+        let declaration = document
+            .ident_at(offset)
+            .and_then(|token| document.declaration(&token.span));
+        Ok(match declaration {
+            Some(span) => location(uri, document, span),
+            None => Tree::Null,
+        })
+    }
+
+    /// Shows how the name under the cursor was declared along with its type and documentation
+    fn hover(&self, params: &Json) -> RequestResult {
+        let (_, document, offset) = self.locate(params)?;
+        let Some(token) = document.ident_at(offset) else {
+            return Ok(Tree::Null);
+        };
+        let Some(declaration) = document.declaration(&token.span) else {
+            return Ok(Tree::Null);
+        };
+
+        let functions = document.functions();
+        let func = functions
+            .iter()
+            .find(|f| f.signature.name.span == *declaration);
+        let text = match func {
+            Some(func) => {
+                let mut text = format!("```starkey\n{}\n```", signature(func));
+                if let Some(doc) = &func.signature.doc {
+                    text.push_str(&format!("\n\n{doc}"));
+                }
+                text
+            }
+            None => {
+                let name = token.kind.value().unwrap_or_default();
+                match document.types.get(declaration) {
+                    Some(ty) => format!("```starkey\n{name} :: {ty}\n```"),
+                    None => format!("```starkey\n{name}\n```"),
+                }
+            }
+        };
+
+        Ok(Tree::Object(vec![
+            (
+                "contents",
+                Tree::Object(vec![("kind", str("markdown")), ("value", Tree::Str(text))]),
+            ),
+            ("range", range(document, &token.span)),
+        ]))
+    }
+
+    fn document_symbols(&self, params: &Json) -> RequestResult {
+        let (_, document) = self.document(params)?;
+        let symbols = document
+            .ast
+            .iter()
+            .flat_map(|node| symbols(document, node))
+            .collect();
+        Ok(Tree::List(symbols))
+    }
+
+    /// Classifies every token, comment and name in the file. Names are coloured by what they
+    /// were declared as, and names following `::`, `->` or `as` are types.
+    fn semantic_tokens(&self, params: &Json) -> RequestResult {
+        let (_, document) = self.document(params)?;
+        // Start, exclusive end, index into `TOKEN_TYPES` and modifier bits
+        let mut found: Vec<(usize, usize, usize, usize)> = Vec::new();
+        let kind = |name: &str| TOKEN_TYPES.iter().position(|t| *t == name).unwrap_or(0);
+
+        let mut previous = None;
+        for token in &document.tokens {
+            for comment in &token.trivia {
+                let modifiers = match comment.kind {
+                    CommentKind::Doc => DOCUMENTATION,
+                    _ => 0,
+                };
+                let (start, end) = (comment.span.start(), comment.span.end() + 1);
+                found.push((start, end, kind("comment"), modifiers));
+            }
+
+            let class = match &token.kind {
+                TokenKind::Ident { .. } => {
+                    let after_type_marker = matches!(
+                        previous,
+                        Some(TokenKind::ColonColon | TokenKind::RArrow | TokenKind::As)
+                    );
+                    let declared = document.declaration(&token.span);
+                    let modifiers = if document.declarations.contains_key(&token.span) {
+                        DECLARATION
+                    } else {
+                        0
+                    };
+                    match declared.and_then(|d| document.declarations.get(d)) {
+                        Some(DeclKind::Function) => Some((kind("function"), modifiers)),
+                        Some(DeclKind::Parameter) => Some((kind("parameter"), modifiers)),
+                        Some(DeclKind::Variable) => Some((kind("variable"), modifiers)),
+                        None if after_type_marker => Some((kind("type"), 0)),
+                        None => Some((kind("variable"), 0)),
+                    }
+                }
+                TokenKind::Number { .. } => Some((kind("number"), 0)),
+                TokenKind::Literal { .. } => Some((kind("string"), 0)),
+                k if is_keyword(k) => Some((kind("keyword"), 0)),
+                k if is_operator(k) => Some((kind("operator"), 0)),
+                _ => None,
+            };
+            if let Some((class, modifiers)) = class {
+                let (start, end) = (token.span.start(), token.span.end() + 1);
+                found.push((start, end, class, modifiers));
+            }
+            if token.kind != TokenKind::Newline {
+                previous = Some(token.kind.clone());
+            }
+        }
+        found.sort_by_key(|(start, ..)| *start);
+
+        // Each token is given relative to the one before it, split into one per line since
+        // clients aren't required to support tokens spanning lines
+        let mut data = Vec::new();
+        let (mut last_line, mut last_column) = (0usize, 0usize);
+        for (start, end, class, modifiers) in found {
+            let src = document.file.src();
+            let end = end.min(src.len());
+            let mut line_start = start;
+            for line in src[start..end].split_inclusive('\n') {
+                let text = line.trim_end_matches(['\n', '\r']);
+                let position = document.file.utf16_position(line_start);
+                let (line_no, column) = (position.line - 1, position.column - 1);
+                line_start += line.len();
+                let length = text.encode_utf16().count();
+                if length == 0 {
+                    continue;
+                }
+                let delta_column = if line_no == last_line {
+                    column - last_column
+                } else {
+                    column
+                };
+                data.extend([line_no - last_line, delta_column, length, class, modifiers]);
+                (last_line, last_column) = (line_no, column);
+            }
+        }
+
+        let data = data.into_iter().map(|n| Tree::Int(n as i128)).collect();
+        Ok(Tree::Object(vec![("data", Tree::List(data))]))
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), (i128, String)> {
+        let uri = params
+            .get(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "missing `textDocument.uri`".to_string()))?;
+        match self.documents.get(uri) {
+            Some(document) => Ok((uri, document)),
+            None => Err((INVALID_PARAMS, format!("`{uri}` is not open"))),
+        }
+    }
+
+    /// The document and byte offset a `TextDocumentPositionParams` points at
+    fn locate<'a>(
+        &'a self,
+        params: &'a Json,
+    ) -> Result<(&'a str, &'a Document, usize), (i128, String)> {
+        let (uri, document) = self.document(params)?;
+        let line = params.get(&["position", "line"]).and_then(Json::as_usize);
+        let column = params
+            .get(&["position", "character"])
+            .and_then(Json::as_usize);
+        match line.zip(column) {
+            Some((line, column)) => {
+                // The protocol counts lines and columns from 0
+                let position = Position {
+                    line: line + 1,
+                    column: column + 1,
+                };
+                Ok((uri, document, document.file.utf16_offset(position)))
+            }
+            None => Err((INVALID_PARAMS, "missing `position`".to_string())),
+        }
+    }
+}
+
+/// Functions in `node` and the functions declared inside of them
+fn symbols(document: &Document, node: &Node) -> Vec<Tree> {
+    let children = || -> Vec<Tree> {
+        bodies(node)
+            .into_iter()
+            .flatten()
+            .flat_map(|node| symbols(document, node))
+            .collect()
+    };
+    match node {
+        Node::Function(func) => {
+            let sig = &func.signature;
+            vec![Tree::Object(vec![
+                ("name", Tree::Str(sig.name.name.clone())),
+                ("detail", Tree::Str(signature(func))),
+                ("kind", Tree::Int(SYMBOL_FUNCTION)),
+                ("range", range(document, &document.function_span(func))),
+                ("selectionRange", range(document, &sig.name.span)),
+                ("children", Tree::List(children())),
+            ])]
+        }
+        _ => children(),
+    }
+}
+
+fn diagnostic(uri: &str, document: &Document, diag: &Diagnostic) -> Tree {
+    let severity = match diag.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut message = diag.message.clone();
+    for note in &diag.notes {
+        message.push_str(&format!("\nnote: {note}"));
+    }
+    let related = diag
+        .secondary
+        .iter()
+        .map(|label| {
+            Tree::Object(vec![
+                ("location", location(uri, document, &label.span)),
+                ("message", Tree::Str(label.message.clone())),
+            ])
+        })
+        .collect();
+
+    Tree::Object(vec![
+        ("range", range(document, &diag.span)),
+        ("severity", Tree::Int(severity)),
+        ("source", str("starkey")),
+        ("message", Tree::Str(message)),
+        ("relatedInformation", Tree::List(related)),
+    ])
+}
+
+fn location(uri: &str, document: &Document, span: &Span) -> Tree {
+    Tree::Object(vec![
+        ("uri", Tree::Str(uri.to_string())),
+        ("range", range(document, span)),
+    ])
+}
+
+/// Spans include their last byte, protocol ranges end just after it
+fn range(document: &Document, span: &Span) -> Tree {
+    let position = |offset: usize| {
+        let position = document.file.utf16_position(offset);
+        Tree::Object(vec![
+            ("line", Tree::Int(position.line as i128 - 1)),
+            ("character", Tree::Int(position.column as i128 - 1)),
+        ])
+    };
+    Tree::Object(vec![
+        ("start", position(span.start())),
+        ("end", position(span.end() + 1)),
+    ])
+}
+
+fn response(id: Tree, result: Tree) -> Tree {
+    Tree::Object(vec![
+        ("jsonrpc", str("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: Tree, code: i128, message: String) -> Tree {
+    Tree::Object(vec![
+        ("jsonrpc", str("2.0")),
+        ("id", id),
+        (
+            "error",
+            Tree::Object(vec![
+                ("code", Tree::Int(code)),
+                ("message", Tree::Str(message)),
+            ]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Tree) -> Tree {
+    Tree::Object(vec![
+        ("jsonrpc", str("2.0")),
+        ("method", str(method)),
+        ("params", params),
+    ])
+}
+
+fn is_keyword(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::If
+            | TokenKind::Else
+            | TokenKind::Elif
+            | TokenKind::For
+            | TokenKind::While
+            | TokenKind::New
+            | TokenKind::Mut
+            | TokenKind::Func
+            | TokenKind::Return
+            | TokenKind::In
+            | TokenKind::Break
+            | TokenKind::Continue
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::And
+            | TokenKind::Or
+            | TokenKind::Not
+            | TokenKind::As
+    )
+}
+
+fn is_operator(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Plus
+            | TokenKind::PlusEqual
+            | TokenKind::Minus
+            | TokenKind::MinusEqual
+            | TokenKind::Star
+            | TokenKind::Slash
+            | TokenKind::Caret
+            | TokenKind::Modulo
+            | TokenKind::RArrow
+            | TokenKind::Ampersand
+            | TokenKind::ColonColon
+            | TokenKind::ColonEqual
+            | TokenKind::DotDot
+            | TokenKind::More
+            | TokenKind::MoreEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual
+            | TokenKind::Equal
+            | TokenKind::EqualEqual
+            | TokenKind::Bang
+            | TokenKind::BangEqual
+    )
+}
+
+fn str(s: &str) -> Tree {
+    Tree::Str(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends one message and reads the replies back the way a client would
+    fn send(server: &mut Server, message: Tree) -> Vec<Json> {
+        let message = Json::parse(&to_json(&message)).expect("message is valid JSON");
+        server
+            .handle(&message)
+            .iter()
+            .map(|reply| Json::parse(&to_json(reply)).expect("reply is valid JSON"))
+            .collect()
+    }
+
+    fn request(id: i128, method: &str, params: Tree) -> Tree {
+        Tree::Object(vec![
+            ("jsonrpc", str("2.0")),
+            ("id", Tree::Int(id)),
+            ("method", str(method)),
+            ("params", params),
+        ])
+    }
+
+    fn open(uri: &str, text: &str) -> Tree {
+        let document = Tree::Object(vec![("uri", str(uri)), ("text", str(text))]);
+        notification(
+            "textDocument/didOpen",
+            Tree::Object(vec![("textDocument", document)]),
+        )
+    }
+
+    fn at(uri: &str, line: i128, character: i128) -> Tree {
+        Tree::Object(vec![
+            ("textDocument", Tree::Object(vec![("uri", str(uri))])),
+            (
+                "position",
+                Tree::Object(vec![
+                    ("line", Tree::Int(line)),
+                    ("character", Tree::Int(character)),
+                ]),
+            ),
+        ])
+    }
+
+    /// `[line, character]` of both ends of a range
+    fn ends(range: Option<&Json>) -> [[usize; 2]; 2] {
+        let position = |end| {
+            let get = |field| {
+                range
+                    .and_then(|r| r.get(&[end, field]))
+                    .and_then(Json::as_usize)
+                    .expect("range has a position")
+            };
+            [get("line"), get("character")]
+        };
+        [position("start"), position("end")]
+    }
+
+    fn initialized() -> Server {
+        let mut server = Server::new();
+        let replies = send(&mut server, request(1, "initialize", Tree::Object(vec![])));
+        let provider = replies[0].get(&["result", "capabilities", "hoverProvider"]);
+        assert_eq!(provider, Some(&Json::Bool(true)));
+        server
+    }
+
+    #[test]
+    fn requests_before_initialize_fail() {
+        let mut server = Server::new();
+        let replies = send(&mut server, request(7, "textDocument/hover", at("a", 0, 0)));
+        assert_eq!(replies[0].get(&["id"]), Some(&Json::Number(7.0)));
+        let code = replies[0].get(&["error", "code"]);
+        assert_eq!(code, Some(&Json::Number(SERVER_NOT_INITIALIZED as f64)));
+    }
+
+    #[test]
+    fn diagnostics_count_utf16_code_units() {
+        let mut server = initialized();
+        let replies = send(
+            &mut server,
+            open("file:///a.sk", "new x = 1\nnew s = \"😀\" + missing\n"),
+        );
+        let method = replies[0].get(&["method"]).and_then(Json::as_str);
+        assert_eq!(method, Some("textDocument/publishDiagnostics"));
+
+        let diagnostics = replies[0]
+            .get(&["params", "diagnostics"])
+            .and_then(Json::as_array);
+        let [diagnostic] = diagnostics.expect("diagnostics are a list") else {
+            panic!("expected one diagnostic, got {diagnostics:?}");
+        };
+        let message = diagnostic.get(&["message"]).and_then(Json::as_str);
+        assert_eq!(message, Some("cannot find `missing` in this scope"));
+        // The emoji is two UTF-16 code units
+        assert_eq!(ends(diagnostic.get(&["range"])), [[1, 15], [1, 22]]);
+    }
+
+    #[test]
+    fn definition_and_hover_find_the_declaration() {
+        const URI: &str = "file:///b.sk";
+        let text = "new x = 1\nfunc f(a :: i64) -> i64 {\n    return a + x\n}\n";
+        let mut server = initialized();
+        let replies = send(&mut server, open(URI, text));
+        let diagnostics = replies[0]
+            .get(&["params", "diagnostics"])
+            .and_then(Json::as_array);
+        assert_eq!(diagnostics, Some(&[][..]));
+
+        // `a` in `return a + x` goes to the parameter
+        let replies = send(
+            &mut server,
+            request(2, "textDocument/definition", at(URI, 2, 11)),
+        );
+        let uri = replies[0].get(&["result", "uri"]).and_then(Json::as_str);
+        assert_eq!(uri, Some(URI));
+        assert_eq!(ends(replies[0].get(&["result", "range"])), [[1, 7], [1, 8]]);
+
+        // `x` in the same line shows its type and covers the whole name
+        let replies = send(
+            &mut server,
+            request(3, "textDocument/hover", at(URI, 2, 15)),
+        );
+        let value = replies[0]
+            .get(&["result", "contents", "value"])
+            .and_then(Json::as_str);
+        assert_eq!(value, Some("```starkey\nx :: i64\n```"));
+        assert_eq!(
+            ends(replies[0].get(&["result", "range"])),
+            [[2, 15], [2, 16]]
+        );
+
+        // Nothing is declared at a keyword
+        let replies = send(
+            &mut server,
+            request(4, "textDocument/definition", at(URI, 2, 5)),
+        );
+        assert_eq!(replies[0].get(&["result"]), Some(&Json::Null));
+    }
+}
@@ -1,38 +1,81 @@
-use std::{env, fs, io};
+use std::{env, panic, process, thread};
 
-use ast::parser::Parser;
-use lexer::lexer::Lexer;
+use cli::{
+    args::{Args, Command, USAGE},
+    driver,
+    exit::Failure,
+};
+use eval::interpreter::STACK_SIZE;
+use lsp::server::Server;
+use repl::session::Repl;
+use trace::tracer::{self, Filter};
 
 mod ast;
+mod check;
+mod cli;
+mod compile;
+mod diagnostics;
+mod eval;
+mod format;
 mod lexer;
+mod lsp;
+mod repl;
+mod resolve;
+mod serialize;
+mod trace;
+mod typeck;
+mod vm;
 
-fn main() -> Result<(), io::Error> {
-    let args: Vec<String> = env::args().collect();
-    let dbga = cfg!(debug_assertions);
-    let path: &str;
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            process::exit(Failure::Usage.code());
+        }
+    };
 
-    // Get path from args
-    // TODO: Debug commands
-    if args.len() >= 2 && dbga {
-        path = &args[1];
-    } else if args.len() >= 1 && !dbga {
-        path = &args[0];
-    } else {
-        eprintln!("Specify a file path");
-        std::process::exit(1);
+    // Silent unless a filter is given
+    if let Some(filter) = args.trace.or_else(Filter::from_env) {
+        tracer::init(filter);
     }
 
-    let src = fs::read_to_string(&path)?;
-    println!("Source file path: {path}");
+    // Each call in an interpreted program takes a few frames of the interpreter, so the program
+    // runs on a thread with a stack big enough for the deepest calls it allows
+    let worker = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(&args));
+    let result = match worker.map(|handle| handle.join()) {
+        Ok(Ok(result)) => result,
+        Ok(Err(panic)) => panic::resume_unwind(panic),
+        Err(e) => {
+            eprintln!("error: cannot start the interpreter: {e}");
+            Err(Failure::Io)
+        }
+    };
 
-    // Initialize lexer for file
-    let mut lexer = Lexer::new(src.as_str());
-    let tokens = lexer.scan();
-    dbg!(&tokens);
-
-    // Initialize parser and parse
-    let mut parser = Parser::new(tokens);
-    dbg!(parser.parse());
+    if let Err(failure) = result {
+        process::exit(failure.code());
+    }
+}
 
-    Ok(())
+/// Runs the command the arguments ask for
+fn run(args: &Args) -> Result<(), Failure> {
+    match &args.path {
+        _ if args.command == Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ if args.command == Command::Lsp => Server::new().run().map_err(|e| {
+            eprintln!("error: {e}");
+            Failure::Io
+        }),
+        Some(path) => driver::execute(args, path),
+        // Without a file, start an interactive session
+        None => Repl::new(args.colour.enabled()).run().map_err(|e| {
+            eprintln!("error: {e}");
+            Failure::Io
+        }),
+    }
 }
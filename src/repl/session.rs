@@ -0,0 +1,266 @@
+use std::{
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{node::Node, parser::Parser},
+    check::checker::Checker,
+    cli::{args::Format, output},
+    compile::compiler::Compiler,
+    diagnostics::{
+        diagnostic::{Diagnostic, Severity},
+        renderer::Renderer,
+    },
+    eval::value::Value,
+    lexer::{error::LexError, lexer::Lexer, source::SourceFile, token::TokenKind},
+    resolve::resolver::{Bindings, Resolver},
+    typeck::infer::TypeChecker,
+    vm::machine::Vm,
+};
+
+const HELP: &str = "\
+:tokens <code>  show the tokens the code is lexed into
+:ast <code>     show the statements the code is parsed into
+:type <code>    show the type of the last statement
+:help           show this message
+:quit           leave the session";
+
+/// Reads statements from stdin and runs them as they are entered. Each input is checked on top
+/// of the declarations the inputs before it made, which the passes keep between inputs. Every
+/// input is appended to one history so that spans from different inputs never overlap and
+/// diagnostics can point at earlier lines.
+pub struct Repl {
+    /// Shared with the renderer while an input is handled, so the rest of the session can change
+    history: Rc<SourceFile>,
+    /// Scopes with every global declared by input that ran successfully
+    resolver: Resolver,
+    checker: Checker,
+    types: TypeChecker,
+    vm: Vm,
+    colour: bool,
+}
+
+impl Repl {
+    pub fn new(colour: bool) -> Self {
+        Self {
+            history: Rc::new(SourceFile::new("<repl>", String::new())),
+            resolver: Resolver::new(),
+            checker: Checker::new(),
+            types: TypeChecker::new(),
+            vm: Vm::new(),
+            colour,
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut input = String::new();
+        loop {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            io::stdout().flush()?;
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                println!();
+                return Ok(());
+            }
+            input.push_str(&line);
+
+            let (command, code) = split_command(&input);
+            if is_incomplete(code) {
+                continue;
+            }
+
+            match command {
+                None if code.trim().is_empty() => {}
+                None => self.execute(code),
+                Some(":tokens") => self.show_tokens(code),
+                Some(":ast") => self.show_ast(code),
+                Some(":type") => self.show_type(code),
+                Some(":help") => println!("{HELP}"),
+                Some(":quit") => return Ok(()),
+                Some(other) => eprintln!("unknown command `{other}`, try `:help`"),
+            }
+            input.clear();
+        }
+    }
+
+    /// Runs the statements in `code` and prints the value of each expression. Input is all or
+    /// nothing: if a statement fails, the globals go back to what they were before the input and
+    /// none of its declarations are kept.
+    fn execute(&mut self, code: &str) {
+        let start = self.append(code);
+        let file = Rc::clone(&self.history);
+        let renderer = Renderer::new(&file, self.colour);
+        let Some((ast, bindings, resolver)) = self.analyse(&file, start, &renderer) else {
+            return;
+        };
+        let types = match self.types.check(&ast, &bindings) {
+            Ok(types) => types,
+            Err(errors) => return self.render(&renderer, errors.iter().map(|e| e.diagnostic())),
+        };
+
+        let saved = self.vm.save();
+        for node in &ast {
+            let result = Compiler::compile_script(node, self.vm.globals(), types)
+                .map_err(|e| e.diagnostic())
+                .and_then(|script| self.vm.run(Rc::new(script)).map_err(|e| e.diagnostic()));
+            match result {
+                Ok(Value::Unit) => {}
+                Ok(value) => println!("{value}"),
+                Err(diag) => {
+                    self.vm.restore(saved);
+                    return eprint!("{}", renderer.render(&diag));
+                }
+            }
+        }
+        self.resolver = resolver;
+    }
+
+    fn show_tokens(&self, code: &str) {
+        let file = SourceFile::new("<repl>", code.to_string());
+        let mut lexer = Lexer::new(file.src());
+        match lexer.scan() {
+            Ok(tokens) => print!("{}", output::tokens(tokens, Format::Text)),
+            Err(errors) => self.report(&file, errors.iter().map(LexError::diagnostic)),
+        }
+    }
+
+    fn show_ast(&self, code: &str) {
+        let file = SourceFile::new("<repl>", code.to_string());
+        let mut lexer = Lexer::new(file.src());
+        let tokens = match lexer.scan() {
+            Ok(tokens) => tokens,
+            Err(errors) => return self.report(&file, errors.iter().map(LexError::diagnostic)),
+        };
+        match Parser::new(tokens).parse() {
+            Ok(ast) => print!("{}", output::ast(&ast, Format::Text)),
+            Err(errors) => self.report(&file, errors.iter().map(|e| e.diagnostic())),
+        }
+    }
+
+    /// Shows the type of the last statement in `code` without running it or keeping anything it
+    /// declares
+    fn show_type(&mut self, code: &str) {
+        let start = self.append(code);
+        let file = Rc::clone(&self.history);
+        let renderer = Renderer::new(&file, self.colour);
+        let Some((ast, bindings, _)) = self.analyse(&file, start, &renderer) else {
+            return;
+        };
+        let Some((last, rest)) = ast.split_last() else {
+            return;
+        };
+
+        if let Err(errors) = self.types.check(rest, &bindings) {
+            return self.render(&renderer, errors.iter().map(|e| e.diagnostic()));
+        }
+        match self.types.type_of(last, &bindings) {
+            Ok(ty) => println!("{ty}"),
+            Err(errors) => self.render(&renderer, errors.iter().map(|e| e.diagnostic())),
+        }
+    }
+
+    /// Adds `code` to the history on a line of its own and returns where it starts
+    fn append(&mut self, code: &str) -> usize {
+        let history = Rc::get_mut(&mut self.history).expect("no input is being handled");
+        let start = history.src().len();
+        history.push_str(code);
+        if !history.src().ends_with('\n') {
+            history.push_str("\n");
+        }
+        start
+    }
+
+    /// Lexes and parses the input at `start` in the history, then resolves and checks it on top
+    /// of what earlier input declared. Returns the statements, what their names refer to and the
+    /// scopes to keep if they run, or `None` if there were any errors.
+    fn analyse(
+        &mut self,
+        file: &SourceFile,
+        start: usize,
+        renderer: &Renderer,
+    ) -> Option<(Vec<Node>, Bindings, Resolver)> {
+        let mut lexer = Lexer::new(file.src());
+        lexer.idx = start;
+        let tokens = match lexer.scan() {
+            Ok(tokens) => tokens,
+            Err(errors) => {
+                self.render(renderer, errors.iter().map(LexError::diagnostic));
+                return None;
+            }
+        };
+        let ast = match Parser::new(tokens).parse() {
+            Ok(ast) => ast,
+            Err(errors) => {
+                self.render(renderer, errors.iter().map(|e| e.diagnostic()));
+                return None;
+            }
+        };
+
+        // Declarations made by input that fails are never bound to, so only the resolver's
+        // scopes have to wait until the input has run
+        let mut resolver = self.resolver.clone();
+        let (bindings, errors) = resolver.resolve(&ast);
+        self.render(renderer, errors.iter().map(|e| e.diagnostic()));
+        if errors.iter().any(|e| e.severity() == Severity::Error) {
+            return None;
+        }
+        if let Err(errors) = self.checker.check(&ast, &bindings) {
+            self.render(renderer, errors.iter().map(|e| e.diagnostic()));
+            return None;
+        }
+        Some((ast, bindings, resolver))
+    }
+
+    fn render(&self, renderer: &Renderer, diags: impl Iterator<Item = Diagnostic>) {
+        for diag in diags {
+            eprint!("{}", renderer.render(&diag));
+        }
+    }
+
+    /// Prints diagnostics about code that is checked without the history
+    fn report(&self, file: &SourceFile, diags: impl Iterator<Item = Diagnostic>) {
+        let renderer = Renderer::new(file, self.colour);
+        for diag in diags {
+            eprint!("{}", renderer.render(&diag));
+        }
+    }
+}
+
+/// Splits a meta command like `:ast` off the front of the input
+fn split_command(input: &str) -> (Option<&str>, &str) {
+    let trimmed = input.trim_start();
+    if !trimmed.starts_with(':') {
+        return (None, input);
+    }
+    match trimmed.split_once(char::is_whitespace) {
+        Some((command, code)) => (Some(command), code),
+        None => (Some(trimmed), ""),
+    }
+}
+
+/// Whether the input needs more lines, because a string or comment is still open or there are
+/// more opening brackets than closing ones
+fn is_incomplete(code: &str) -> bool {
+    let mut lexer = Lexer::new(code);
+    match lexer.scan() {
+        Ok(tokens) => {
+            let depth: i32 = tokens
+                .iter()
+                .map(|t| match t.kind {
+                    TokenKind::LPar | TokenKind::LBrac | TokenKind::LCurl => 1,
+                    TokenKind::RPar | TokenKind::RBrac | TokenKind::RCurl => -1,
+                    _ => 0,
+                })
+                .sum();
+            depth > 0
+        }
+        Err(errors) => errors.iter().any(|e| {
+            matches!(
+                e,
+                LexError::UnterminatedString { .. } | LexError::UnterminatedComment { .. }
+            )
+        }),
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::{
+    diagnostics::diagnostic::{Diagnostic, Severity},
+    lexer::token::Span,
+};
+
+/// Problems found while binding names to their declarations. Shadowing is only a warning, the
+/// rest are errors.
+#[derive(Debug, Clone)]
+pub enum ResolveError {
+    /// A name that doesn't refer to any declaration in scope
+    Undefined { name: String, span: Span },
+
+    /// A name declared twice in the same scope
+    Duplicate {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+
+    /// A declaration that hides one with the same name in an enclosing scope
+    Shadowed {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+}
+
+impl ResolveError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::Undefined { span, .. } => span,
+            Self::Duplicate { span, .. } => span,
+            Self::Shadowed { span, .. } => span,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Shadowed { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::new(self.severity(), self.to_string(), self.span().clone());
+        match self {
+            Self::Undefined { .. } => diag.with_label("not found in this scope"),
+            Self::Duplicate { previous, .. } => diag
+                .with_label("declared again here")
+                .with_secondary(previous.clone(), "first declared here"),
+            Self::Shadowed { previous, .. } => diag
+                .with_label("this declaration hides the other one")
+                .with_secondary(previous.clone(), "shadowed declaration")
+                .with_note("rename one of them if this isn't intended"),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Undefined { name, .. } => write!(f, "cannot find `{name}` in this scope"),
+            Self::Duplicate { name, .. } => {
+                write!(f, "`{name}` is declared more than once in the same scope")
+            }
+            Self::Shadowed { name, .. } => write!(f, "`{name}` shadows an earlier declaration"),
+        }
+    }
+}
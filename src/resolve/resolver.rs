@@ -0,0 +1,247 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::node::{FunctionExpr, Ident, Node},
+    lexer::token::Span,
+};
+
+use super::error::ResolveError;
+
+/// Maps the span of every name that was used to the span of the declaration it refers to
+pub type Bindings = HashMap<Span, Span>;
+
+/// Names declared in one scope, mapped to where they were declared
+type Scope = HashMap<String, Span>;
+
+/// Binds every use of a name to its declaration, following the same scoping as the interpreter:
+/// blocks and loops open nested scopes and function bodies only see the global scope, their own
+/// name and their own locals.
+///
+/// Function bodies are resolved once the code around them has been, so they can use anything
+/// declared at the top level, even below them. Globals are kept between calls to `resolve`, so
+/// code resolved later, like the next input to the REPL, can use them. `Checker` and
+/// `TypeChecker` look names up through the bindings this returns, and follow the same two rules.
+#[derive(Clone)]
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    deferred: Vec<Rc<FunctionExpr>>,
+    bindings: Bindings,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            deferred: Vec::new(),
+            bindings: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Returns what every name refers to along with every error and warning, sorted by where
+    /// they are in the file
+    pub fn resolve(&mut self, ast: &[Node]) -> (Bindings, Vec<ResolveError>) {
+        for node in ast {
+            self.visit(node);
+        }
+
+        // Bodies of functions declared inside of other bodies are added as they are found
+        while let Some(func) = self.deferred.pop() {
+            self.resolve_body(&func);
+        }
+
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| e.span().start());
+        (std::mem::take(&mut self.bindings), errors)
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Ident(ident) => self.use_name(ident),
+            Node::Group(inner) => self.visit(inner),
+            Node::UnaryExpr(e) => self.visit(&e.rhs),
+            Node::BinaryExpr(e) => {
+                self.visit(&e.lhs);
+                self.visit(&e.rhs);
+            }
+            Node::Cast(c) => self.visit(&c.value),
+            Node::Let(l) => {
+                // The value is resolved first so `new x = x + 1` uses the outer `x`
+                self.visit(&l.value);
+                self.declare(&l.name);
+            }
+            Node::Assign(a) => {
+                self.visit(&a.value);
+                self.use_name(&a.target);
+            }
+            Node::Function(func) => {
+                // Declared before the body so the function can call itself
+                self.declare(&func.signature.name);
+                self.deferred.push(Rc::clone(func));
+            }
+            Node::Call(c) => {
+                self.use_name(&c.callee);
+                for arg in &c.args {
+                    self.visit(arg);
+                }
+            }
+            Node::Return(r) => {
+                if let Some(value) = &r.value {
+                    self.visit(value);
+                }
+            }
+            Node::Block(body) => self.visit_block(body),
+            Node::If(stmt) => {
+                for branch in &stmt.branches {
+                    self.visit(&branch.cond);
+                    self.visit_block(&branch.body);
+                }
+                if let Some(body) = &stmt.otherwise {
+                    self.visit_block(body);
+                }
+            }
+            Node::While(stmt) => {
+                self.visit(&stmt.cond);
+                self.visit_block(&stmt.body);
+            }
+            Node::For(stmt) => {
+                self.visit(&stmt.start);
+                self.visit(&stmt.end);
+
+                // The loop variable lives in its own scope around the body
+                self.scopes.push(HashMap::new());
+                self.declare(&stmt.var);
+                self.visit_block(&stmt.body);
+                self.scopes.pop();
+            }
+            Node::Integer(..)
+            | Node::Number(..)
+            | Node::Bool(_)
+            | Node::Str(_)
+            | Node::Break(_)
+            | Node::Continue(_) => {}
+        }
+    }
+
+    /// Resolves a function body on top of the global scope and the function's own name, which
+    /// lets functions declared inside of other bodies call themselves
+    fn resolve_body(&mut self, func: &FunctionExpr) {
+        let saved = self.scopes.split_off(1);
+
+        let name = &func.signature.name;
+        self.scopes
+            .push(HashMap::from([(name.name.clone(), name.span.clone())]));
+        self.scopes.push(HashMap::new());
+        for param in &func.signature.params {
+            self.declare(&param.name);
+        }
+        for node in &func.body {
+            self.visit(node);
+        }
+
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+    }
+
+    fn visit_block(&mut self, body: &[Node]) {
+        self.scopes.push(HashMap::new());
+        for node in body {
+            self.visit(node);
+        }
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Ident) {
+        if let Some(previous) = self.scopes.last().and_then(|s| s.get(&name.name)) {
+            self.errors.push(ResolveError::Duplicate {
+                name: name.name.clone(),
+                span: name.span.clone(),
+                previous: previous.clone(),
+            });
+        } else if let Some(previous) = self.lookup(&name.name) {
+            self.errors.push(ResolveError::Shadowed {
+                name: name.name.clone(),
+                span: name.span.clone(),
+                previous: previous.clone(),
+            });
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.name.clone(), name.span.clone());
+        }
+    }
+
+    fn use_name(&mut self, name: &Ident) {
+        match self.lookup(&name.name).cloned() {
+            Some(declared) => {
+                self.bindings.insert(name.span.clone(), declared);
+            }
+            None => self.errors.push(ResolveError::Undefined {
+                name: name.name.clone(),
+                span: name.span.clone(),
+            }),
+        }
+    }
+
+    /// Finds the closest visible declaration of `name`
+    fn lookup(&self, name: &str) -> Option<&Span> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    fn resolve(src: &str) -> (Bindings, Vec<ResolveError>) {
+        let (_, ast) = parse(src);
+        Resolver::new().resolve(&ast)
+    }
+
+    /// Span of the `n`th occurrence of `name` in `src`
+    fn span(src: &str, name: &str, n: usize) -> Span {
+        let start = src.match_indices(name).nth(n).expect("name occurs").0;
+        Span::from(start, name.len())
+    }
+
+    #[test]
+    fn uses_bind_to_the_closest_declaration() {
+        let src = "new x = 1\n{\n    new x = x + 1\n    x\n}\nx\n";
+        let (bindings, errors) = resolve(src);
+
+        // The inner value still sees the outer `x`, the declaration only starts after it
+        assert_eq!(bindings[&span(src, "x", 2)], span(src, "x", 0));
+        assert_eq!(bindings[&span(src, "x", 3)], span(src, "x", 1));
+        assert_eq!(bindings[&span(src, "x", 4)], span(src, "x", 0));
+        assert!(matches!(errors[..], [ResolveError::Shadowed { .. }]));
+    }
+
+    #[test]
+    fn redeclaring_in_one_scope_is_an_error() {
+        let (_, errors) = resolve("new x = 1\nnew x = 2\n");
+        assert!(matches!(errors[..], [ResolveError::Duplicate { .. }]));
+    }
+
+    #[test]
+    fn nested_functions_can_call_themselves() {
+        let src = "func outer() {\n    func inner(n :: i64) -> i64 {\n        return inner(n)\n    }\n}\n";
+        let (bindings, errors) = resolve(src);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(bindings[&span(src, "inner", 1)], span(src, "inner", 0));
+    }
+
+    #[test]
+    fn function_bodies_only_see_globals_and_their_own_locals() {
+        let src = "func outer() {\n    new local = 1\n    func inner() { local }\n}\n";
+        let (_, errors) = resolve(src);
+        assert!(matches!(&errors[..], [ResolveError::Undefined { name, .. }] if name == "local"));
+
+        // Globals declared below a function can still be used inside of it
+        let src = "func f() -> i64 { return later }\nnew later = 2\n";
+        let (bindings, errors) = resolve(src);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(bindings[&span(src, "later", 0)], span(src, "later", 1));
+    }
+}
@@ -0,0 +1,59 @@
+use super::tree::Tree;
+
+/// Writes a tree as JSON on a single line. Floats that JSON can't represent are written as
+/// `null`.
+pub fn to_json(tree: &Tree) -> String {
+    let mut out = String::new();
+    write_tree(tree, &mut out);
+    out
+}
+
+fn write_tree(tree: &Tree, out: &mut String) {
+    match tree {
+        Tree::Null => out.push_str("null"),
+        Tree::Bool(v) => out.push_str(&v.to_string()),
+        Tree::Int(v) => out.push_str(&v.to_string()),
+        Tree::Float(v) if v.is_finite() => out.push_str(&format!("{v:?}")),
+        Tree::Float(_) => out.push_str("null"),
+        Tree::Str(s) => write_str(s, out),
+        Tree::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_tree(item, out);
+            }
+            out.push(']');
+        }
+        Tree::Object(fields) => {
+            out.push('{');
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(name, out);
+                out.push(':');
+                write_tree(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Writes `s` as a quoted string with JSON escapes, which the S-expression writer shares
+pub fn write_str(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
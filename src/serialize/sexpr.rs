@@ -0,0 +1,51 @@
+use super::{json::write_str, tree::Tree};
+
+/// Writes a tree as an S-expression on a single line. Lists are written as `(item ...)` and
+/// objects as `(kind :field value ...)`, where the head is the object's `kind` field if it has
+/// one.
+pub fn to_sexpr(tree: &Tree) -> String {
+    let mut out = String::new();
+    write_tree(tree, &mut out);
+    out
+}
+
+fn write_tree(tree: &Tree, out: &mut String) {
+    match tree {
+        Tree::Null => out.push_str("nil"),
+        Tree::Bool(v) => out.push_str(&v.to_string()),
+        Tree::Int(v) => out.push_str(&v.to_string()),
+        Tree::Float(v) => out.push_str(&format!("{v:?}")),
+        Tree::Str(s) => write_str(s, out),
+        Tree::List(items) => {
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_tree(item, out);
+            }
+            out.push(')');
+        }
+        Tree::Object(fields) => {
+            out.push('(');
+            let fields = match fields.as_slice() {
+                [("kind", Tree::Str(kind)), rest @ ..] => {
+                    out.push_str(kind);
+                    out.push(' ');
+                    rest
+                }
+                fields => fields,
+            };
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                out.push(':');
+                out.push_str(name);
+                out.push(' ');
+                write_tree(value, out);
+            }
+            out.push(')');
+        }
+    }
+}
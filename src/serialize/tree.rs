@@ -0,0 +1,241 @@
+use crate::{
+    ast::node::{op_str, unary_op_str, Branch, FunctionExpr, Ident, Node, ParameterExpr},
+    lexer::token::{Comment, CommentKind, Span, Token},
+};
+
+/// A format neutral description of a value, which the JSON and S-expression writers both work
+/// from so that they always agree on the shape of the output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tree {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    List(Vec<Tree>),
+    /// Named fields in a fixed order, AST nodes and tokens start with a `kind` field
+    Object(Vec<(&'static str, Tree)>),
+}
+
+/// Conversion into a `Tree`, implemented for the output of the lexer and the parser
+pub trait ToTree {
+    fn to_tree(&self) -> Tree;
+}
+
+impl<T: ToTree> ToTree for [T] {
+    fn to_tree(&self) -> Tree {
+        Tree::List(self.iter().map(ToTree::to_tree).collect())
+    }
+}
+
+impl<T: ToTree> ToTree for Option<T> {
+    fn to_tree(&self) -> Tree {
+        self.as_ref().map_or(Tree::Null, ToTree::to_tree)
+    }
+}
+
+impl<T: ToTree + ?Sized> ToTree for &T {
+    fn to_tree(&self) -> Tree {
+        (**self).to_tree()
+    }
+}
+
+impl ToTree for Span {
+    fn to_tree(&self) -> Tree {
+        Tree::Object(vec![
+            ("start", Tree::Int(self.start() as i128)),
+            ("end", Tree::Int(self.end() as i128)),
+        ])
+    }
+}
+
+/// Tokens with a value, like identifiers and literals, get a `value` field
+impl ToTree for Token {
+    fn to_tree(&self) -> Tree {
+        let mut fields = vec![("kind", str(self.kind.name()))];
+        if let Some(value) = self.kind.value() {
+            fields.push(("value", str(value)));
+        }
+        fields.push(("span", self.span.to_tree()));
+        fields.push(("trivia", self.trivia.to_tree()));
+        Tree::Object(fields)
+    }
+}
+
+impl ToTree for Comment {
+    fn to_tree(&self) -> Tree {
+        let kind = match self.kind {
+            CommentKind::Line => "line",
+            CommentKind::Block => "block",
+            CommentKind::Doc => "doc",
+        };
+        Tree::Object(vec![
+            ("kind", str(kind)),
+            ("text", str(&self.text)),
+            ("span", self.span.to_tree()),
+        ])
+    }
+}
+
+impl ToTree for Ident {
+    fn to_tree(&self) -> Tree {
+        Tree::Object(vec![
+            ("name", str(&self.name)),
+            ("span", self.span.to_tree()),
+        ])
+    }
+}
+
+impl ToTree for Node {
+    fn to_tree(&self) -> Tree {
+        let (kind, fields) = match self {
+            Node::Integer(v, ty) => (
+                "integer",
+                vec![("value", Tree::Int(*v)), ("type", str(ty.name()))],
+            ),
+            Node::Number(v, ty) => (
+                "number",
+                vec![("value", Tree::Float(*v)), ("type", str(ty.name()))],
+            ),
+            Node::Bool(v) => ("bool", vec![("value", Tree::Bool(*v))]),
+            Node::Str(v) => ("string", vec![("value", str(v))]),
+            Node::Ident(ident) => (
+                "ident",
+                vec![("name", str(&ident.name)), ("span", ident.span.to_tree())],
+            ),
+            Node::Group(inner) => ("group", vec![("expr", inner.to_tree())]),
+            Node::UnaryExpr(e) => (
+                "unary",
+                vec![
+                    ("op", str(unary_op_str(e.op))),
+                    ("rhs", e.rhs.to_tree()),
+                    ("span", e.span.to_tree()),
+                ],
+            ),
+            Node::BinaryExpr(e) => (
+                "binary",
+                vec![
+                    ("op", str(op_str(e.op))),
+                    ("lhs", e.lhs.to_tree()),
+                    ("rhs", e.rhs.to_tree()),
+                    ("span", e.span.to_tree()),
+                ],
+            ),
+            Node::Cast(c) => (
+                "cast",
+                vec![
+                    ("value", c.value.to_tree()),
+                    ("type", c.ty.to_tree()),
+                    ("span", c.span.to_tree()),
+                ],
+            ),
+            Node::Let(l) => (
+                "let",
+                vec![
+                    ("name", l.name.to_tree()),
+                    ("mutable", Tree::Bool(l.mutable)),
+                    ("annotation", l.annotation.to_tree()),
+                    ("value", l.value.to_tree()),
+                ],
+            ),
+            Node::Assign(a) => {
+                let op = match a.op {
+                    b'=' => "=".to_string(),
+                    op => format!("{}=", op_str(op)),
+                };
+                (
+                    "assign",
+                    vec![
+                        ("target", a.target.to_tree()),
+                        ("op", Tree::Str(op)),
+                        ("value", a.value.to_tree()),
+                        ("span", a.span.to_tree()),
+                    ],
+                )
+            }
+            Node::Function(func) => ("function", function_fields(func)),
+            Node::Call(c) => (
+                "call",
+                vec![
+                    ("callee", c.callee.to_tree()),
+                    ("args", c.args.to_tree()),
+                    ("span", c.span.to_tree()),
+                ],
+            ),
+            Node::Return(r) => (
+                "return",
+                vec![
+                    ("value", r.value.as_deref().to_tree()),
+                    ("span", r.span.to_tree()),
+                ],
+            ),
+            Node::Block(body) => ("block", vec![("body", body.to_tree())]),
+            Node::If(stmt) => (
+                "if",
+                vec![
+                    ("branches", stmt.branches.to_tree()),
+                    ("otherwise", stmt.otherwise.as_deref().to_tree()),
+                ],
+            ),
+            Node::While(stmt) => (
+                "while",
+                vec![
+                    ("cond", stmt.cond.to_tree()),
+                    ("body", stmt.body.to_tree()),
+                    ("span", stmt.span.to_tree()),
+                ],
+            ),
+            Node::For(stmt) => (
+                "for",
+                vec![
+                    ("var", stmt.var.to_tree()),
+                    ("start", stmt.start.to_tree()),
+                    ("end", stmt.end.to_tree()),
+                    ("body", stmt.body.to_tree()),
+                    ("span", stmt.span.to_tree()),
+                ],
+            ),
+            Node::Break(span) => ("break", vec![("span", span.to_tree())]),
+            Node::Continue(span) => ("continue", vec![("span", span.to_tree())]),
+        };
+
+        let mut object = vec![("kind", str(kind))];
+        object.extend(fields);
+        Tree::Object(object)
+    }
+}
+
+impl ToTree for Branch {
+    fn to_tree(&self) -> Tree {
+        Tree::Object(vec![
+            ("cond", self.cond.to_tree()),
+            ("body", self.body.to_tree()),
+            ("span", self.span.to_tree()),
+        ])
+    }
+}
+
+impl ToTree for ParameterExpr {
+    fn to_tree(&self) -> Tree {
+        Tree::Object(vec![
+            ("name", self.name.to_tree()),
+            ("type", self.annotation.to_tree()),
+            ("mutable", Tree::Bool(self.mutable)),
+        ])
+    }
+}
+
+fn function_fields(func: &FunctionExpr) -> Vec<(&'static str, Tree)> {
+    let sig = &func.signature;
+    vec![
+        ("name", sig.name.to_tree()),
+        ("params", sig.params.to_tree()),
+        ("returns", sig.returns.to_tree()),
+        ("doc", sig.doc.as_deref().map_or(Tree::Null, str)),
+        ("body", func.body.to_tree()),
+    ]
+}
+
+fn str(s: &str) -> Tree {
+    Tree::Str(s.to_string())
+}
@@ -0,0 +1,148 @@
+use std::{env, fmt, sync::OnceLock};
+
+/// How much detail a message carries. A filter set to a level shows that level and every level
+/// above it in this list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::Info => write!(f, "info"),
+            Self::Debug => write!(f, "debug"),
+            Self::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+/// The part of the pipeline a message comes from, each one is filtered separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lexer,
+    Parser,
+    /// Both the interpreter and the VM
+    Eval,
+}
+
+impl Phase {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lexer" => Some(Self::Lexer),
+            "parser" => Some(Self::Parser),
+            "eval" => Some(Self::Eval),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lexer => write!(f, "lexer"),
+            Self::Parser => write!(f, "parser"),
+            Self::Eval => write!(f, "eval"),
+        }
+    }
+}
+
+/// The most detailed level shown for each phase, `None` turns a phase off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Filter {
+    levels: [Option<Level>; 3],
+}
+
+impl Filter {
+    /// Name of the environment variable read when `--trace` isn't given
+    pub const ENV: &'static str = "STARKEY_TRACE";
+
+    /// Parses a comma separated list of directives. A level on its own applies to every phase, a
+    /// phase on its own shows everything from it, and `phase=level` or `phase=off` sets one
+    /// phase, e.g. `info,lexer=trace,eval=off`. Returns the directive that couldn't be parsed.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let level = |name: &str| match name {
+                "off" => Ok(None),
+                name => Level::from_name(name)
+                    .map(Some)
+                    .ok_or(directive.to_string()),
+            };
+
+            match directive.split_once('=') {
+                Some((phase, name)) => {
+                    let phase = Phase::from_name(phase).ok_or(directive.to_string())?;
+                    filter.levels[phase as usize] = level(name)?;
+                }
+                None => match Phase::from_name(directive) {
+                    Some(phase) => filter.levels[phase as usize] = Some(Level::Trace),
+                    None => filter.levels = [level(directive)?; 3],
+                },
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Reads the filter from `STARKEY_TRACE`, an invalid value is reported and ignored
+    pub fn from_env() -> Option<Self> {
+        let spec = env::var(Self::ENV).ok()?;
+        match Self::parse(&spec) {
+            Ok(filter) => Some(filter),
+            Err(directive) => {
+                eprintln!("warning: ignoring `{directive}` in {}", Self::ENV);
+                None
+            }
+        }
+    }
+
+    pub fn allows(&self, phase: Phase, level: Level) -> bool {
+        self.levels[phase as usize].is_some_and(|max| level <= max)
+    }
+}
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+/// Sets the filter for the rest of the program, until this is called nothing is traced
+pub fn init(filter: Filter) {
+    let _ = FILTER.set(filter);
+}
+
+pub fn enabled(phase: Phase, level: Level) -> bool {
+    FILTER.get().is_some_and(|f| f.allows(phase, level))
+}
+
+pub fn emit(phase: Phase, level: Level, message: fmt::Arguments) {
+    eprintln!("[{phase} {level}] {message}");
+}
+
+/// Writes a message to stderr if the filter allows it, the arguments are only formatted when it
+/// does: `trace!(Lexer, Trace, "token {i} at {span}")`
+#[macro_export]
+macro_rules! trace {
+    ($phase:ident, $level:ident, $($arg:tt)*) => {{
+        use $crate::trace::tracer::{Level, Phase};
+        if $crate::trace::tracer::enabled(Phase::$phase, Level::$level) {
+            $crate::trace::tracer::emit(Phase::$phase, Level::$level, format_args!($($arg)*));
+        }
+    }};
+}
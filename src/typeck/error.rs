@@ -0,0 +1,111 @@
+use std::fmt;
+
+use crate::{diagnostics::diagnostic::Diagnostic, lexer::token::Span};
+
+use super::types::Type;
+
+/// Errors found while inferring and checking types
+#[derive(Debug, Clone)]
+pub enum TypeError {
+    /// A value of type `found` was used where `expected` was required
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+
+    /// An annotation that doesn't name a type
+    UnknownType { name: String, span: Span },
+
+    /// An operator applied to a type it doesn't support
+    InvalidOperand {
+        op: &'static str,
+        ty: Type,
+        span: Span,
+    },
+
+    /// A binary operator applied to numbers of two types that don't convert to each other
+    MismatchedOperands {
+        op: &'static str,
+        lhs: Type,
+        rhs: Type,
+        span: Span,
+    },
+
+    /// An integer raised to a constant negative power, which fails when it runs
+    NegativeExponent { span: Span },
+
+    /// A constant that doesn't fit in the integer type it is converted to
+    OutOfRange { value: i128, ty: Type, span: Span },
+
+    /// `as` applied to something that isn't a number
+    InvalidCast { from: Type, to: Type, span: Span },
+
+    /// A function with a return type whose body can end without returning
+    MissingReturn { name: String, ty: Type, span: Span },
+}
+
+impl TypeError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::Mismatch { span, .. } => span,
+            Self::UnknownType { span, .. } => span,
+            Self::InvalidOperand { span, .. } => span,
+            Self::MismatchedOperands { span, .. } => span,
+            Self::NegativeExponent { span } => span,
+            Self::OutOfRange { span, .. } => span,
+            Self::InvalidCast { span, .. } => span,
+            Self::MissingReturn { span, .. } => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string(), self.span().clone());
+        match self {
+            Self::Mismatch { expected, .. } => diag.with_label(format!("expected `{expected}`")),
+            Self::UnknownType { .. } => diag.with_label("not a type").with_note(
+                "the types are `i8`..`i64`, `u8`..`u64`, `f32`, `f64`, `bool` and `str`",
+            ),
+            Self::InvalidOperand { ty, .. } => diag.with_label(format!("operand is `{ty}`")),
+            Self::MismatchedOperands { lhs, rhs, .. } => diag
+                .with_label(format!("`{lhs}` on the left, `{rhs}` on the right"))
+                .with_note("use `as` to convert one side to the other's type"),
+            Self::NegativeExponent { .. } => diag
+                .with_label("exponent is negative")
+                .with_note("convert the base with `as f64` for a fractional result"),
+            Self::OutOfRange { ty, .. } => diag.with_label(format!("does not fit in `{ty}`")),
+            Self::InvalidCast { from, .. } => diag.with_label(format!("value is `{from}`")),
+            Self::MissingReturn { ty, .. } => diag
+                .with_label(format!("expected to return `{ty}`"))
+                .with_note("every path through the body has to end in a `return`"),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched types: expected `{expected}`, found `{found}`"
+            ),
+            Self::UnknownType { name, .. } => write!(f, "unknown type `{name}`"),
+            Self::InvalidOperand { op, ty, .. } => {
+                write!(f, "cannot apply `{op}` to a value of type `{ty}`")
+            }
+            Self::MismatchedOperands { op, lhs, rhs, .. } => {
+                write!(f, "cannot apply `{op}` to `{lhs}` and `{rhs}`")
+            }
+            Self::NegativeExponent { .. } => {
+                write!(f, "an integer can't be raised to a negative power")
+            }
+            Self::OutOfRange { value, ty, .. } => write!(f, "`{value}` is out of range for `{ty}`"),
+            Self::InvalidCast { from, to, .. } => write!(f, "cannot cast `{from}` to `{to}`"),
+            Self::MissingReturn { name, .. } => {
+                write!(f, "function `{name}` may end without returning a value")
+            }
+        }
+    }
+}
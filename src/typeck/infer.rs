@@ -0,0 +1,593 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        node::{
+            op_str, unary_op_str, Assign, BinaryExpr, CallExpr, Cast, ForStmt, FunctionExpr, Ident,
+            IfStmt, Let, Node, Return, UnaryExpr,
+        },
+        number::NumType,
+    },
+    lexer::token::Span,
+    resolve::resolver::Bindings,
+};
+
+use super::{error::TypeError, types::Type};
+
+/// The type of every expression and declaration that has a span, keyed by that span. The
+/// compiler converts numbers to the types of the variables, parameters and loop counters they
+/// are stored in, and the language server shows them in hovers.
+pub type Types = HashMap<Span, Type>;
+
+/// Infers the type of every expression and checks them against annotations, return types,
+/// conditions and the operators they are used with. Names the resolver couldn't bind are given
+/// the `Unknown` type, bodies and declarations are otherwise handled the way `Resolver`
+/// describes.
+#[derive(Clone)]
+pub struct TypeChecker {
+    /// What every name used so far refers to
+    bindings: Bindings,
+    deferred: Vec<Rc<FunctionExpr>>,
+    /// Return type of each function we are inside of
+    returns: Vec<Type>,
+    types: Types,
+    errors: Vec<TypeError>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            deferred: Vec::new(),
+            returns: Vec::new(),
+            types: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Checks `ast` using the bindings the resolver found for it, returning every type found so
+    /// far
+    pub fn check(&mut self, ast: &[Node], bindings: &Bindings) -> Result<&Types, Vec<TypeError>> {
+        self.bind(bindings);
+        for node in ast {
+            self.infer(node);
+        }
+        self.infer_bodies();
+
+        if self.errors.is_empty() {
+            Ok(&self.types)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Infers the type of one top level node, like `check` but returning the node's own type
+    pub fn type_of(&mut self, node: &Node, bindings: &Bindings) -> Result<Type, Vec<TypeError>> {
+        self.bind(bindings);
+        let ty = self.infer(node);
+        self.infer_bodies();
+        if self.errors.is_empty() {
+            Ok(ty)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn infer(&mut self, node: &Node) -> Type {
+        match node {
+            Node::Integer(_, ty) | Node::Number(_, ty) => Type::Num(*ty),
+            Node::Bool(_) => Type::Bool,
+            Node::Str(_) => Type::Str,
+            Node::Ident(ident) => {
+                let ty = self.lookup(ident);
+                self.record(&ident.span, ty)
+            }
+            Node::Group(inner) => self.infer(inner),
+            Node::UnaryExpr(e) => self.infer_unary(e),
+            Node::BinaryExpr(e) => self.infer_binary(e),
+            Node::Cast(c) => self.infer_cast(c),
+            Node::Let(l) => self.infer_let(l),
+            Node::Assign(a) => self.infer_assign(a),
+            Node::Function(func) => self.infer_function(func),
+            Node::Call(c) => self.infer_call(c),
+            Node::Return(r) => self.infer_return(r),
+            Node::Block(body) => self.infer_block(body),
+            Node::If(stmt) => self.infer_if(stmt),
+            Node::While(stmt) => {
+                self.condition(&stmt.cond, &stmt.span);
+                self.infer_block(&stmt.body)
+            }
+            Node::For(stmt) => self.infer_for(stmt),
+            Node::Break(_) | Node::Continue(_) => Type::Unit,
+        }
+    }
+
+    fn infer_unary(&mut self, e: &UnaryExpr) -> Type {
+        let rhs = self.infer(&e.rhs);
+        let ty = match (e.op, rhs) {
+            (_, Type::Unknown) => Type::Unknown,
+            (b'-', ty @ Type::Num(_)) => ty,
+            (b'!', Type::Bool) => Type::Bool,
+            (b'#', Type::Str) => Type::Num(NumType::I64),
+            (b'&', ty) => Type::Pointer(Box::new(ty)),
+            (b'*', Type::Pointer(to)) => *to,
+            (op, ty) => self.error(TypeError::InvalidOperand {
+                op: unary_op_str(op),
+                ty,
+                span: e.span.clone(),
+            }),
+        };
+        self.record(&e.span, ty)
+    }
+
+    fn infer_binary(&mut self, e: &BinaryExpr) -> Type {
+        let lhs = self.infer(&e.lhs);
+        let rhs = self.infer(&e.rhs);
+        let ty = binary_type(e.op, lhs, rhs, &e.span).unwrap_or_else(|e| self.error(e));
+
+        // Integer powers stay integers, so a negative exponent can only fail at runtime
+        let integer = matches!(ty, Type::Num(ty) if !ty.is_float());
+        if e.op == b'^' && integer && constant_integer(&e.rhs).is_some_and(|v| v < 0) {
+            self.errors.push(TypeError::NegativeExponent {
+                span: e.span.clone(),
+            });
+        }
+        self.record(&e.span, ty)
+    }
+
+    fn infer_cast(&mut self, c: &Cast) -> Type {
+        let value = self.infer(&c.value);
+        // The checker reports cast targets that aren't number types
+        let Some(to) = NumType::from_name(&c.ty.name).map(Type::Num) else {
+            return self.record(&c.span, Type::Unknown);
+        };
+
+        let ty = match value {
+            Type::Num(_) | Type::Unknown => to,
+            from => self.error(TypeError::InvalidCast {
+                from,
+                to,
+                span: c.span.clone(),
+            }),
+        };
+        self.record(&c.span, ty)
+    }
+
+    fn infer_let(&mut self, l: &Let) -> Type {
+        let value = self.infer(&l.value);
+        let ty = match &l.annotation {
+            Some(annotation) => {
+                let ty = self.annotation(annotation);
+                self.expect(&ty, value, &annotation.span);
+                ty
+            }
+            None => value,
+        };
+        self.record(&l.name.span, ty);
+        Type::Unit
+    }
+
+    /// Assignments can't change the type of a variable
+    fn infer_assign(&mut self, a: &Assign) -> Type {
+        let target = self.lookup(&a.target);
+        let value = self.infer(&a.value);
+        let value = match a.op {
+            b'=' => value,
+            op => binary_type(op, target.clone(), value, &a.span).unwrap_or_else(|e| self.error(e)),
+        };
+        self.expect(&target, value, &a.span);
+        Type::Unit
+    }
+
+    fn infer_function(&mut self, func: &Rc<FunctionExpr>) -> Type {
+        let sig = &func.signature;
+        let params: Vec<Type> = sig
+            .params
+            .iter()
+            .map(|param| self.annotation(&param.annotation))
+            .collect();
+        let returns = match &sig.returns {
+            Some(annotation) => self.annotation(annotation),
+            None => Type::Unit,
+        };
+
+        for (param, ty) in sig.params.iter().zip(&params) {
+            self.record(&param.name.span, ty.clone());
+        }
+        let ty = Type::Func {
+            params,
+            returns: Box::new(returns.clone()),
+        };
+        self.record(&sig.name.span, ty);
+        self.deferred.push(Rc::clone(func));
+
+        if returns != Type::Unit && !always_returns(&func.body) {
+            self.errors.push(TypeError::MissingReturn {
+                name: sig.name.name.clone(),
+                ty: returns,
+                span: sig.name.span.clone(),
+            });
+        }
+        Type::Unit
+    }
+
+    fn infer_call(&mut self, c: &CallExpr) -> Type {
+        let args: Vec<Type> = c.args.iter().map(|arg| self.infer(arg)).collect();
+
+        // Calling something that isn't a function, or with the wrong number of arguments, is
+        // reported by the checker
+        let ty = match self.lookup(&c.callee) {
+            Type::Func { params, returns } => {
+                for (param, arg) in params.iter().zip(args) {
+                    self.expect(param, arg, &c.span);
+                }
+                *returns
+            }
+            _ => Type::Unknown,
+        };
+        self.record(&c.span, ty)
+    }
+
+    fn infer_return(&mut self, r: &Return) -> Type {
+        let value = match &r.value {
+            Some(value) => self.infer(value),
+            None => Type::Unit,
+        };
+        // `return` outside of a function is reported by the checker
+        if let Some(expected) = self.returns.last().cloned() {
+            self.expect(&expected, value, &r.span);
+        }
+        Type::Unit
+    }
+
+    fn infer_block(&mut self, body: &[Node]) -> Type {
+        for node in body {
+            self.infer(node);
+        }
+        Type::Unit
+    }
+
+    /// Checks the bodies of the functions declared so far, and of those declared inside of them
+    fn infer_bodies(&mut self) {
+        while let Some(func) = self.deferred.pop() {
+            let returns = match &func.signature.returns {
+                Some(annotation) => Type::from_name(&annotation.name).unwrap_or(Type::Unknown),
+                None => Type::Unit,
+            };
+            self.returns.push(returns);
+            for node in &func.body {
+                self.infer(node);
+            }
+            self.returns.pop();
+        }
+    }
+
+    fn infer_if(&mut self, stmt: &IfStmt) -> Type {
+        for branch in &stmt.branches {
+            self.condition(&branch.cond, &branch.span);
+            self.infer_block(&branch.body);
+        }
+        if let Some(body) = &stmt.otherwise {
+            self.infer_block(body);
+        }
+        Type::Unit
+    }
+
+    fn infer_for(&mut self, stmt: &ForStmt) -> Type {
+        let start = self.infer(&stmt.start);
+        let end = self.infer(&stmt.end);
+
+        // The loop variable has the type of the start of the range
+        let ty = match start {
+            Type::Num(ty) if !ty.is_float() => Type::Num(ty),
+            Type::Unknown => Type::Unknown,
+            ty => self.error(TypeError::InvalidOperand {
+                op: "..",
+                ty,
+                span: stmt.span.clone(),
+            }),
+        };
+        if !matches!(end, Type::Num(ty) if !ty.is_float()) && end != Type::Unknown {
+            self.errors.push(TypeError::InvalidOperand {
+                op: "..",
+                ty: end,
+                span: stmt.span.clone(),
+            });
+        } else if !end.coerces_to(&ty) {
+            self.expect(&ty, end, &stmt.end_span);
+        } else if let (Type::Num(num), Some(value)) = (&ty, constant_integer(&stmt.end)) {
+            // The end is converted to the type of the loop variable when the loop starts
+            if num.fit(value).is_none() {
+                self.errors.push(TypeError::OutOfRange {
+                    value,
+                    ty: ty.clone(),
+                    span: stmt.end_span.clone(),
+                });
+            }
+        }
+
+        self.record(&stmt.var.span, ty);
+        self.infer_block(&stmt.body);
+        Type::Unit
+    }
+
+    fn condition(&mut self, cond: &Node, span: &Span) {
+        let ty = self.infer(cond);
+        self.expect(&Type::Bool, ty, span);
+    }
+
+    /// Reports a mismatch if `found` can't be used where `expected` is required
+    fn expect(&mut self, expected: &Type, found: Type, span: &Span) {
+        if !found.coerces_to(expected) {
+            self.errors.push(TypeError::Mismatch {
+                expected: expected.clone(),
+                found,
+                span: span.clone(),
+            });
+        }
+    }
+
+    fn annotation(&mut self, annotation: &Ident) -> Type {
+        Type::from_name(&annotation.name).unwrap_or_else(|| {
+            self.error(TypeError::UnknownType {
+                name: annotation.name.clone(),
+                span: annotation.span.clone(),
+            })
+        })
+    }
+
+    /// Records an error and returns the type to carry on with
+    fn error(&mut self, e: TypeError) -> Type {
+        self.errors.push(e);
+        Type::Unknown
+    }
+
+    fn record(&mut self, span: &Span, ty: Type) -> Type {
+        self.types.insert(span.clone(), ty.clone());
+        ty
+    }
+
+    fn bind(&mut self, bindings: &Bindings) {
+        self.bindings
+            .extend(bindings.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// The type recorded for the declaration a name refers to
+    fn lookup(&self, name: &Ident) -> Type {
+        self.bindings
+            .get(&name.span)
+            .and_then(|declared| self.types.get(declared))
+            .cloned()
+            .unwrap_or(Type::Unknown)
+    }
+}
+
+/// The type of a binary expression, following the same rules as the interpreter: numbers of the
+/// same kind must have the same type unless one of them is `i64` or `f64`, mixing integers with
+/// floats gives a float, strings can be joined with `+` and compared, `bool`s can be checked for
+/// equality and `and`/`or` take `bool`s
+fn binary_type(op: u8, lhs: Type, rhs: Type, span: &Span) -> Result<Type, TypeError> {
+    let invalid = |ty: Type| TypeError::InvalidOperand {
+        op: op_str(op),
+        ty,
+        span: span.clone(),
+    };
+
+    if lhs == Type::Unknown || rhs == Type::Unknown {
+        return Ok(if is_comparison(op) || matches!(op, b'&' | b'|') {
+            Type::Bool
+        } else {
+            Type::Unknown
+        });
+    }
+
+    match op {
+        b'&' | b'|' => match (lhs, rhs) {
+            (Type::Bool, Type::Bool) => Ok(Type::Bool),
+            (Type::Bool, ty) | (ty, _) => Err(invalid(ty)),
+        },
+        _ if is_comparison(op) => match (lhs, rhs) {
+            (Type::Num(_), Type::Num(_)) | (Type::Str, Type::Str) => Ok(Type::Bool),
+            (Type::Bool, Type::Bool) if matches!(op, b'=' | b'!') => Ok(Type::Bool),
+            (Type::Num(_), ty) | (Type::Str, ty) | (ty, _) => Err(invalid(ty)),
+        },
+        _ => match (lhs, rhs) {
+            (Type::Str, Type::Str) if op == b'+' => Ok(Type::Str),
+            (Type::Num(a), Type::Num(b)) => match (a.is_float(), b.is_float()) {
+                (true, false) => Ok(Type::Num(a)),
+                (false, true) => Ok(Type::Num(b)),
+                _ if a == b || b == NumType::I64 || b == NumType::F64 => Ok(Type::Num(a)),
+                _ if a == NumType::I64 || a == NumType::F64 => Ok(Type::Num(b)),
+                _ => Err(TypeError::MismatchedOperands {
+                    op: op_str(op),
+                    lhs: Type::Num(a),
+                    rhs: Type::Num(b),
+                    span: span.clone(),
+                }),
+            },
+            // Like the interpreter, a string next to anything else blames the other side
+            (Type::Str, ty) if op == b'+' => Err(invalid(ty)),
+            (Type::Num(_), ty) | (ty, _) => Err(invalid(ty)),
+        },
+    }
+}
+
+/// The value of an integer literal, which may be negated or in parentheses
+fn constant_integer(node: &Node) -> Option<i128> {
+    match node {
+        Node::Integer(v, _) => Some(*v),
+        Node::Group(inner) => constant_integer(inner),
+        Node::UnaryExpr(e) if e.op == b'-' => constant_integer(&e.rhs).map(|v| -v),
+        _ => None,
+    }
+}
+
+fn is_comparison(op: u8) -> bool {
+    matches!(op, b'=' | b'!' | b'<' | b'L' | b'>' | b'G')
+}
+
+/// Whether running `body` always ends in a `return`. Loops might not run at all so returns
+/// inside of them don't count.
+fn always_returns(body: &[Node]) -> bool {
+    body.iter().any(|node| match node {
+        Node::Return(_) => true,
+        Node::Block(inner) => always_returns(inner),
+        Node::If(stmt) => {
+            stmt.branches.iter().all(|b| always_returns(&b.body))
+                && stmt.otherwise.as_deref().is_some_and(always_returns)
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::parse, resolve::resolver::Resolver};
+
+    fn check(src: &str) -> Result<Types, Vec<TypeError>> {
+        let (_, ast) = parse(src);
+        let (bindings, _) = Resolver::new().resolve(&ast);
+        TypeChecker::new().check(&ast, &bindings).cloned()
+    }
+
+    fn errors(src: &str) -> Vec<TypeError> {
+        check(src).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn negative_integer_exponent_is_rejected() {
+        let found = errors("new x :: i64 = 2 ^ -1\n");
+        assert!(matches!(found[..], [TypeError::NegativeExponent { .. }]));
+        let found = errors("2 ^ (-(3))\n");
+        assert!(matches!(found[..], [TypeError::NegativeExponent { .. }]));
+    }
+
+    #[test]
+    fn float_and_positive_exponents_are_allowed() {
+        assert!(check("2.0 ^ -1\n").is_ok());
+        assert!(check("2 as f32 ^ -1\n").is_ok());
+        assert!(check("2 ^ 3\n").is_ok());
+        assert!(check("2 ^ -(-3)\n").is_ok());
+    }
+
+    #[test]
+    fn annotations_must_match_values() {
+        let found = errors("new x :: i64 = \"one\"\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Num(_),
+                found: Type::Str,
+                ..
+            }]
+        ));
+        let found = errors("new x :: int = 1\n");
+        assert!(matches!(&found[..], [TypeError::UnknownType { name, .. }] if name == "int"));
+    }
+
+    #[test]
+    fn operators_reject_unsupported_operands() {
+        let found = errors("\"a\" - 1\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::InvalidOperand {
+                op: "-",
+                ty: Type::Str,
+                ..
+            }]
+        ));
+        let found = errors("-true\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::InvalidOperand { ty: Type::Bool, .. }]
+        ));
+        let found = errors("1u8 + 1i32\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::MismatchedOperands { op: "+", .. }]
+        ));
+        let found = errors("true as i64\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::InvalidCast {
+                from: Type::Bool,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn conditions_must_be_bool() {
+        let found = errors("if 1 { }\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Num(_),
+                ..
+            }]
+        ));
+        let found = errors("while \"yes\" { }\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Bool,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn range_ends_must_fit_the_loop_variable() {
+        let found = errors("new n :: u16 = 3\nfor i in 0u8..n { }\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Num(NumType::U8),
+                found: Type::Num(NumType::U16),
+                ..
+            }]
+        ));
+        let found = errors("for i in 250u8..260 { }\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::OutOfRange { value: 260, .. }]
+        ));
+        assert!(check("for i in 250u8..256 - 1 { }\nfor i in 0..10u8 as i64 { }\n").is_ok());
+    }
+
+    #[test]
+    fn functions_must_return_their_type() {
+        let found = errors("func f(a :: i64) -> i64 {\n    if a > 0 { return a }\n}\n");
+        assert!(matches!(&found[..], [TypeError::MissingReturn { name, .. }] if name == "f"));
+        let found = errors("func f() -> bool {\n    return 1\n}\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Bool,
+                ..
+            }]
+        ));
+        assert!(check(
+            "func f(a :: i64) -> i64 {\n    if a > 0 { return a } else { return 0 }\n}\n"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn arguments_must_match_parameters() {
+        let found = errors("func f(a :: i64, b :: bool) { }\nf(1, 2)\n");
+        assert!(matches!(
+            &found[..],
+            [TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Num(_),
+                ..
+            }]
+        ));
+        // Errors inside bodies are found too, and reported once
+        let found = errors("func f() {\n    new x :: str = 1 + 1\n}\n");
+        assert_eq!(found.len(), 1, "{found:?}");
+    }
+}
@@ -0,0 +1,3 @@
+pub mod error;
+pub mod infer;
+pub mod types;
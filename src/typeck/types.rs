@@ -0,0 +1,73 @@
+use std::fmt;
+
+use crate::ast::number::NumType;
+
+/// The static type of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Num(NumType),
+    Bool,
+    Str,
+    /// The type of statements and functions that don't return anything
+    Unit,
+    /// Made by `&`
+    Pointer(Box<Type>),
+    Func {
+        params: Vec<Type>,
+        returns: Box<Type>,
+    },
+    /// The type of something that already failed to check. It is compatible with everything so
+    /// one mistake isn't reported again by everything that uses it.
+    Unknown,
+}
+
+impl Type {
+    /// Looks up a type written in an annotation, like the `u8` in `new x :: u8 = 1`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bool" => Some(Self::Bool),
+            "str" => Some(Self::Str),
+            _ => NumType::from_name(name).map(Self::Num),
+        }
+    }
+
+    /// Whether a value of this type can be stored where `target` is expected. `i64` and `f64` are
+    /// the types of literals without a suffix, so like in the interpreter they convert to any
+    /// integer or float type, and integers convert to floats.
+    pub fn coerces_to(&self, target: &Type) -> bool {
+        match (self, target) {
+            (Self::Unknown, _) | (_, Self::Unknown) => true,
+            (Self::Num(from), Self::Num(to)) => {
+                from == to
+                    || (*from == NumType::I64 && !to.is_float())
+                    || (*from == NumType::F64 && to.is_float())
+                    || (!from.is_float() && to.is_float())
+            }
+            (Self::Pointer(from), Self::Pointer(to)) => from.coerces_to(to) && to.coerces_to(from),
+            (from, to) => from == to,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Num(ty) => write!(f, "{ty}"),
+            Self::Bool => write!(f, "bool"),
+            Self::Str => write!(f, "str"),
+            Self::Unit => write!(f, "()"),
+            Self::Pointer(to) => write!(f, "&{to}"),
+            Self::Func { params, returns } => {
+                write!(f, "func(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{param}")?;
+                }
+                write!(f, ") -> {returns}")
+            }
+            Self::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
+}
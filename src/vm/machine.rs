@@ -0,0 +1,536 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    ast::number::NumType,
+    compile::{
+        chunk::{Function, OpCode},
+        globals::Globals,
+    },
+    eval::{
+        environment::Slot,
+        error::RuntimeError,
+        interpreter::MAX_CALL_DEPTH,
+        ops::{binary_op, cast, convert, logical_operand, unary_op},
+        value::Value,
+    },
+    lexer::token::Span,
+    trace,
+};
+
+/// A function that is running
+struct Frame {
+    function: Rc<Function>,
+    ip: usize,
+    /// Index of the stack slot holding the function, its parameters and locals come after it
+    base: usize,
+}
+
+/// The storage and value of every global at one point, which `Vm::restore` goes back to
+pub type Saved = Vec<Option<(Slot, Value)>>;
+
+/// Runs compiled functions on a stack of values. Globals outlive each run so that top level
+/// statements can be run one after another, as long as they are all compiled with the VM's
+/// `globals`.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    /// Storage for each global by the index `names` gave it, empty until it is defined
+    globals: Vec<Option<Slot>>,
+    names: Globals,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Vec::new(),
+            names: Globals::new(),
+        }
+    }
+
+    /// The global slots that code run on this VM has to be compiled with
+    pub fn globals(&mut self) -> &mut Globals {
+        &mut self.names
+    }
+
+    /// Remembers what every global holds, so the effects of running something can be undone
+    pub fn save(&self) -> Saved {
+        self.globals
+            .iter()
+            .map(|slot| slot.as_ref().map(|s| (Rc::clone(s), s.borrow().clone())))
+            .collect()
+    }
+
+    /// Puts every global back the way it was when `saved` was made. Globals defined since then
+    /// are removed, the rest get their old values back in their old storage so pointers to them
+    /// still work.
+    pub fn restore(&mut self, saved: Saved) {
+        self.globals.truncate(saved.len());
+        for (slot, saved) in self.globals.iter_mut().zip(saved) {
+            *slot = saved.map(|(storage, value)| {
+                *storage.borrow_mut() = value;
+                storage
+            });
+        }
+    }
+
+    /// Runs a compiled top level statement and returns its value
+    pub fn run(&mut self, script: Rc<Function>) -> Result<Value, RuntimeError> {
+        self.stack.push(Value::Compiled(Rc::clone(&script)));
+        self.frames.push(Frame {
+            function: script,
+            ip: 0usize,
+            base: 0usize,
+        });
+
+        let result = self.execute();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let start = self.frame().ip;
+            let byte = self.read_byte();
+            let Some(op) = OpCode::from_byte(byte) else {
+                unreachable!("invalid opcode {byte}");
+            };
+            trace!(
+                Eval,
+                Trace,
+                "{} {start:04} {op:?} with {} values on the stack",
+                self.frame().function.name,
+                self.stack.len()
+            );
+
+            match op {
+                OpCode::Constant => {
+                    let index = self.read_u16() as usize;
+                    let value = self.frame().function.chunk.constants[index].clone();
+                    self.stack.push(value);
+                }
+                OpCode::Unit => self.stack.push(Value::Unit),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+
+                OpCode::GetLocal => {
+                    let slot = self.slot();
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.slot();
+                    self.stack[slot] = self.pop();
+                }
+                OpCode::GetGlobal => {
+                    let value = self.global(start)?.borrow().clone();
+                    self.stack.push(value);
+                }
+                OpCode::SetGlobal => {
+                    let value = self.pop();
+                    *self.global(start)?.borrow_mut() = value;
+                }
+                OpCode::DefineGlobal => {
+                    let index = self.read_u16() as usize;
+                    let value = self.pop();
+                    if index >= self.globals.len() {
+                        self.globals.resize(index + 1, None);
+                    }
+                    self.globals[index] = Some(Rc::new(RefCell::new(value)));
+                }
+                OpCode::RefGlobal => {
+                    let slot = Rc::clone(self.global(start)?);
+                    self.stack.push(Value::Pointer(slot));
+                }
+
+                OpCode::Box => {
+                    let value = self.pop();
+                    self.stack
+                        .push(Value::Pointer(Rc::new(RefCell::new(value))));
+                }
+                OpCode::Store => {
+                    let value = self.pop();
+                    // Only boxed locals are stored to, so this is always a pointer
+                    if let Value::Pointer(slot) = self.pop() {
+                        *slot.borrow_mut() = value;
+                    }
+                }
+                OpCode::Coerce => {
+                    let value = self.pop();
+                    let value = match self.pop() {
+                        // Numbers keep the type of the variable they are assigned to
+                        Value::Integer(_, ty) | Value::Number(_, ty) => {
+                            convert(value, ty, &self.span(start))?
+                        }
+                        _ => value,
+                    };
+                    self.stack.push(value);
+                }
+
+                OpCode::Unary => {
+                    let op = self.read_byte();
+                    let rhs = self.pop();
+                    self.stack.push(unary_op(op, rhs, &self.span(start))?);
+                }
+                OpCode::Binary => {
+                    let op = self.read_byte();
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(binary_op(op, lhs, rhs, &self.span(start))?);
+                }
+                OpCode::Logical => {
+                    let op = self.read_byte();
+                    let value = self.pop();
+                    let value = logical_operand(op, value, &self.span(start))?;
+                    self.stack.push(Value::Bool(value));
+                }
+                OpCode::Cast => {
+                    let ty = self.read_type();
+                    let value = self.pop();
+                    self.stack.push(cast(value, ty, &self.span(start))?);
+                }
+                OpCode::Convert => {
+                    let ty = self.read_type();
+                    let value = self.pop();
+                    self.stack.push(convert(value, ty, &self.span(start))?);
+                }
+
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    match self.pop() {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => self.frame_mut().ip += offset,
+                        v => {
+                            return Err(RuntimeError::InvalidCondition {
+                                ty: v.type_name(),
+                                span: self.span(start),
+                            })
+                        }
+                    }
+                }
+                OpCode::JumpIfFalseKeep | OpCode::JumpIfTrueKeep => {
+                    let offset = self.read_u16() as usize;
+                    // `Logical` has already checked this is a `bool`
+                    let jump_on = op == OpCode::JumpIfTrueKeep;
+                    if matches!(self.stack.last(), Some(Value::Bool(v)) if *v == jump_on) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+
+                OpCode::RangeBound => {
+                    if let Some(v) = self.stack.last() {
+                        if !matches!(v, Value::Integer(..)) {
+                            return Err(RuntimeError::InvalidOperand {
+                                op: "..",
+                                ty: v.type_name(),
+                                span: self.span(start),
+                            });
+                        }
+                    }
+                }
+                OpCode::ForLoop => {
+                    let slot = self.slot();
+                    let offset = self.read_u16() as usize;
+                    match (&self.stack[slot], &self.stack[slot + 1]) {
+                        (Value::Integer(i, ty), Value::Integer(end, _)) if i < end => {
+                            self.stack.push(Value::Integer(*i, *ty));
+                        }
+                        _ => self.frame_mut().ip += offset,
+                    }
+                }
+                OpCode::ForStep => {
+                    let slot = self.slot();
+                    if let Value::Integer(i, _) = &mut self.stack[slot] {
+                        *i += 1;
+                    }
+                }
+
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    // The callee's name is only read if it can't be called
+                    self.frame_mut().ip += 2;
+                    self.call(argc, start)?;
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let Some(frame) = self.frames.pop() else {
+                        unreachable!("returned without a frame");
+                    };
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, argc: usize, start: usize) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - argc - 1;
+        let Value::Compiled(function) = &self.stack[base] else {
+            let chunk = &self.frame().function.chunk;
+            let name = match &chunk.constants[chunk.read_u16(start + 2) as usize] {
+                Value::Str(name) => name.clone(),
+                _ => unreachable!("callee names are strings"),
+            };
+            return Err(RuntimeError::NotCallable {
+                name,
+                span: self.span(start),
+            });
+        };
+
+        if function.arity != argc {
+            return Err(RuntimeError::ArityMismatch {
+                expected: function.arity,
+                found: argc,
+                span: self.span(start),
+            });
+        }
+        // The frame running the top level statement doesn't count
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow {
+                span: self.span(start),
+            });
+        }
+
+        trace!(
+            Eval,
+            Debug,
+            "call {} at depth {} ({})",
+            function.name,
+            self.frames.len() - 1,
+            self.span(start)
+        );
+        self.frames.push(Frame {
+            function: Rc::clone(function),
+            ip: 0usize,
+            base,
+        });
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("the VM is running a function")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("the VM is running a function")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    /// Span of the instruction starting at `start` in the running function
+    fn span(&self, start: usize) -> Span {
+        self.frame().function.chunk.spans[start].clone()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    /// Reads a slot operand and returns its index in the stack
+    fn slot(&mut self) -> usize {
+        let slot = self.read_u16() as usize;
+        self.frame().base + slot
+    }
+
+    fn read_type(&mut self) -> NumType {
+        NumType::ALL[self.read_byte() as usize]
+    }
+
+    /// Reads a global operand and returns its storage, the name is only looked up if the global
+    /// hasn't been defined yet
+    fn global(&mut self, start: usize) -> Result<&Slot, RuntimeError> {
+        let index = self.read_u16();
+        match self.globals.get(index as usize) {
+            Some(Some(slot)) => Ok(slot),
+            _ => Err(RuntimeError::UndefinedVariable {
+                name: self.names.name(index).to_string(),
+                span: self.span(start),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{
+        ast::parse,
+        compile::compiler::Compiler,
+        eval::interpreter::{Interpreter, STACK_SIZE},
+        resolve::resolver::Resolver,
+        typeck::infer::TypeChecker,
+    };
+
+    /// What running each statement printed, or the error that stopped the program
+    type Outcome = Vec<Result<String, String>>;
+
+    /// Runs `src` with both the interpreter and the VM, on a thread with the stack `main` uses
+    fn run_both(src: &'static str) -> (Outcome, Outcome) {
+        let worker = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let (_, ast) = parse(src);
+                // Some programs fail to type check on purpose, to test the errors they run into
+                let (bindings, _) = Resolver::new().resolve(&ast);
+                let types = TypeChecker::new()
+                    .check(&ast, &bindings)
+                    .cloned()
+                    .unwrap_or_default();
+
+                let mut interpreter = Interpreter::new();
+                let mut interpreted = Vec::new();
+                for node in &ast {
+                    let result = interpreter.eval(node);
+                    let stop = result.is_err();
+                    interpreted.push(result.map(|v| v.to_string()).map_err(|e| e.to_string()));
+                    if stop {
+                        break;
+                    }
+                }
+
+                let mut vm = Vm::new();
+                let mut compiled = Vec::new();
+                for node in &ast {
+                    let script =
+                        Compiler::compile_script(node, vm.globals(), &types).expect("compiles");
+                    let result = vm.run(Rc::new(script));
+                    let stop = result.is_err();
+                    compiled.push(result.map(|v| v.to_string()).map_err(|e| e.to_string()));
+                    if stop {
+                        break;
+                    }
+                }
+                (interpreted, compiled)
+            });
+        worker.expect("thread starts").join().expect("no panic")
+    }
+
+    fn assert_agree(src: &'static str) {
+        let (interpreted, compiled) = run_both(src);
+        assert_eq!(interpreted, compiled, "for:\n{src}");
+    }
+
+    #[test]
+    fn arithmetic_and_types() {
+        assert_agree("1 + 2 * 3\n7 / 2\n7.0 / 2\n-2 ^ 2\n2 ^ 3 ^ 2\n10 % 3\n");
+        assert_agree("new x :: u8 = 250\nx + 5\n3 as f32 / 2\n255u8 as i8\n1.9 as i64\n");
+        assert_agree("\"ab\" + \"cd\"\n#\"héllo\"\n\"a\" < \"b\"\ntrue == false\n");
+        assert_agree("new x :: u8 = 250\nx + 10\n");
+        assert_agree("2 ^ 62\nnew e = -1\n2 ^ e\n");
+
+        let (interpreted, compiled) = run_both("-9223372036854775808\n-128i8 - 1i8\n");
+        assert_eq!(interpreted, compiled);
+        assert!(
+            matches!(&interpreted[..], [Ok(min), Err(e)] if min == "-9223372036854775808" && e.contains("overflow")),
+            "{interpreted:?}"
+        );
+    }
+
+    #[test]
+    fn variables_and_pointers() {
+        assert_agree("new mut x = 1\nx += 2\nx -= 5\nx\nnew p = &x\n*p\nx = 10\n*p\n");
+        assert_agree("new mut f :: f32 = 1\nf = 2\nf\nnew mut i :: i8 = 1\ni = 3\ni\n");
+        assert_agree("func bump(p :: i64) -> i64 {\n    new mut local = p\n    new q = &local\n    local = local + 1\n    return *q\n}\nbump(4)\n");
+    }
+
+    #[test]
+    fn control_flow() {
+        let src = "new mut total = 0
+for i in 0..10 {
+    if i == 2 { continue }
+    if i == 7 { break }
+    total += i
+}
+total
+new mut n = 0
+while n < 5 { n += 1 }
+n
+if n > 3 and not (n == 4) or false { 1 } elif n == 4 { 2 } else { 3 }
+";
+        assert_agree(src);
+    }
+
+    #[test]
+    fn range_ends_take_the_counters_type() {
+        assert_agree("new mut last = 0\nfor i in 250u8..255 { last = i as i64 }\nlast\n");
+        assert_agree("new mut last = 0u16\nfor i in 0u16..300 { last = i }\nlast\n");
+
+        let (interpreted, compiled) = run_both(
+            "new end = 260\nnew mut last = 0\nfor i in 250u8..end { last = i as i64 }\nlast\n",
+        );
+        assert_eq!(interpreted, compiled);
+        assert!(
+            matches!(&interpreted[..], [Ok(_), Ok(_), Err(e)] if e.contains("overflow")),
+            "{interpreted:?}"
+        );
+    }
+
+    #[test]
+    fn functions_and_recursion() {
+        let src = "func fib(n :: i64) -> i64 {
+    if n < 2 { return n }
+    return fib(n - 1) + fib(n - 2)
+}
+fib(15)
+func outer(n :: i64) -> i64 {
+    func inner(k :: i64) -> i64 {
+        if k == 0 { return 0 }
+        return inner(k - 1) + 2
+    }
+    return inner(n)
+}
+outer(5)
+func half(x :: f64) -> f64 { return x / 2 }
+half(3)
+func deep(n :: i64) -> i64 {
+    if n == 0 { return 0 }
+    return deep(n - 1) + 1
+}
+deep(999)
+";
+        assert_agree(src);
+    }
+
+    #[test]
+    fn errors() {
+        assert_agree("1 / 0\n");
+        assert_agree("new x :: u8 = 200\nx + 100\n");
+        assert_agree("func f(n :: i64) -> i64 { return f(n + 1) }\nf(0)\n");
+        assert_agree("new e = -2\n3 ^ e\n");
+        assert_agree("1 + true\n");
+        assert_agree("func f() -> i64 { return later }\nf()\nnew later = 1\n");
+    }
+}